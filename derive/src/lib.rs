@@ -1,70 +1,269 @@
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::spanned::Spanned;
 use syn::*;
 
-#[proc_macro_derive(Extend, attributes(prop_data))]
+/// Derives `Extend` for a struct with exactly one field marked with `#[prop_data]`, which must be
+/// of type `PropertyData<Self>`. Enums are also supported, as long as every variant has exactly
+/// one such field.
+///
+/// By default, each type gets its own `Subject`. A type can instead use a subject shared with
+/// other types by adding an `#[extend(subject = K)]` attribute, where `K` is any type used to
+/// identify the subject. This also implements `ExtendShared<K>` for the type, so that a
+/// `SharedProperty<K, _>` can be used on it.
+///
+/// Alternatively, a struct field whose type implements `Extend` can be marked with
+/// `#[prop_data(forward)]`. The struct then uses the same `Subject` and `PropertyData` as the
+/// field, so a property of the struct and a property of the field's type can both be used on the
+/// same object, without conflicting with each other.
+///
+/// ```
+/// use dynprops::{Dynamic, Extend, Property};
+///
+/// #[derive(Extend)]
+/// struct Wrapper(#[prop_data(forward)] Dynamic);
+///
+/// let mut inner_prop = Property::<Dynamic, u32>::new();
+/// let mut outer_prop = Property::<Wrapper, u32>::new();
+/// let wrapper = Wrapper(Dynamic::new());
+/// inner_prop.set(&wrapper.0, 1);
+/// outer_prop.set(&wrapper, 2);
+/// assert_eq!(*inner_prop.get(&wrapper.0), 1);
+/// assert_eq!(*outer_prop.get(&wrapper), 2);
+/// ```
+///
+/// ```
+/// use dynprops::{Extend, PropertyData};
+///
+/// #[derive(Extend)]
+/// enum Shape {
+///     Circle { radius: f32, #[prop_data] prop_data: PropertyData<Shape> },
+///     Square(f32, #[prop_data] PropertyData<Shape>),
+/// }
+/// ```
+///
+/// Deriving fails for types without a `#[prop_data]` field, such as unit structs:
+///
+/// ```compile_fail
+/// #[derive(dynprops::Extend)]
+/// struct Unit;
+/// ```
+///
+/// For enums, every variant needs its own `#[prop_data]` field:
+///
+/// ```compile_fail
+/// use dynprops::{Extend, PropertyData};
+///
+/// #[derive(Extend)]
+/// enum Shape {
+///     Circle { radius: f32, #[prop_data] prop_data: PropertyData<Shape> },
+///     Point,
+/// }
+/// ```
+///
+/// Unions aren't supported:
+///
+/// ```compile_fail
+/// use dynprops::{Extend, PropertyData};
+///
+/// #[derive(Extend)]
+/// union Bits {
+///     int: u32,
+///     float: f32,
+/// }
+/// ```
+#[proc_macro_derive(Extend, attributes(prop_data, extend))]
 pub fn derive_extend(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let name = input.ident;
-    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
-    let prop_data = match prop_data(&input.data) {
+    let name = &input.ident;
+    let (prop_data, forward) = match prop_data(&input) {
         Ok(prop_data) => prop_data,
         Err(err) => return TokenStream::from(err.to_compile_error()),
     };
+    let shared = match shared_subject(&input.attrs) {
+        Ok(Some(key)) if forward.is_some() => {
+            return TokenStream::from(
+                syn::Error::new(
+                    key.span(),
+                    "A shared subject can't be used with #[prop_data(forward)]",
+                )
+                .to_compile_error(),
+            )
+        }
+        Ok(shared) => shared,
+        Err(err) => return TokenStream::from(err.to_compile_error()),
+    };
+
+    // Generic types need a distinct subject for each instantiation, which can't be stored in a
+    // static within a generic function. Lifetime parameters don't matter, since they don't
+    // distinguish instantiations at runtime.
+    let mut generics = input.generics.clone();
+    let subject = if let Some(key) = &shared {
+        quote! {
+            ::dynprops::Subject::shared::<#key>()
+        }
+    } else if let Some(inner_ty) = &forward {
+        generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote! { #inner_ty: Extend });
+        quote! {
+            <#inner_ty as Extend>::subject()
+        }
+    } else if generics.type_params().next().is_none() && generics.const_params().next().is_none() {
+        quote! {
+            static VALUE: ::std::sync::OnceLock<::dynprops::Subject> =
+                ::std::sync::OnceLock::new();
+            VALUE.get_or_init(::dynprops::Subject::new)
+        }
+    } else {
+        generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote! { Self: 'static });
+        quote! {
+            ::dynprops::Subject::of::<Self>()
+        }
+    };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let extend_shared = shared.map(|key| {
+        quote! {
+            unsafe impl #impl_generics ::dynprops::ExtendShared<#key>
+                for #name #ty_generics #where_clause {}
+        }
+    });
     TokenStream::from(quote! {
         unsafe impl #impl_generics Extend for #name #ty_generics #where_clause {
             fn subject() -> &'static ::dynprops::Subject {
-                static ONCE: ::std::sync::Once = ::std::sync::Once::new();
-                static mut VALUE: *mut ::dynprops::Subject = 0 as *mut ::dynprops::Subject;
-                unsafe {
-                    ONCE.call_once(|| {
-                        let subject = ::dynprops::Subject::new();
-                        VALUE = ::std::boxed::Box::into_raw(::std::boxed::Box::new(subject));
-                    });
-                    &*VALUE
-                }
+                #subject
             }
 
             fn prop_data(&self) -> &::dynprops::PropertyData<#name #ty_generics> {
                 #prop_data
             }
         }
+
+        #extend_shared
     })
 }
 
-/// Gets the expression used to access the property data field from a value of a given data type.
-fn prop_data(data: &Data) -> syn::Result<TokenStream2> {
-    match data {
-        Data::Struct(ref data) => {
-            match data.fields {
-                Fields::Named(ref fields) => {
-                    let mut prop_data_fields = fields.named.iter().filter(|field| {
-                        field
-                            .attrs
-                            .iter()
-                            .any(|attr| attr.path.is_ident("prop_data"))
-                    });
-                    match as_singleton(&mut prop_data_fields) {
-                        Some(prop_data_field) => {
-                            let name = prop_data_field.ident.as_ref().unwrap();
-                            Ok(quote! { &self.#name })
-                        }
-                        None => Err(syn::Error::new(
-                            data.fields.span(),
-                            "Exactly one field must be marked with a #[prop_data] attribute",
-                        )),
-                    }
-                }
-                Fields::Unnamed(_) => todo!(),
-                Fields::Unit => todo!(), // TODO: Error here
+/// Gets the type given by an `#[extend(subject = K)]` attribute, which identifies the shared
+/// subject to use.
+fn shared_subject(attrs: &[Attribute]) -> syn::Result<Option<Type>> {
+    let mut res = None;
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("extend")) {
+        res = Some(attr.parse_args_with(|input: parse::ParseStream| {
+            let name: Ident = input.parse()?;
+            if name != "subject" {
+                return Err(syn::Error::new(name.span(), "Expected `subject`"));
             }
+            input.parse::<Token![=]>()?;
+            input.parse::<Type>()
+        })?);
+    }
+    Ok(res)
+}
+
+/// Gets the expression used to access the property data field from a value of a given data type,
+/// along with the type of the field if it is marked with `#[prop_data(forward)]`.
+fn prop_data(input: &DeriveInput) -> syn::Result<(TokenStream2, Option<Type>)> {
+    match &input.data {
+        Data::Struct(data) => {
+            let (member, forward) = prop_data_member(&data.fields, input.ident.span())?;
+            match forward {
+                Some(inner_ty) => Ok((
+                    quote! {
+                        // SAFETY: `Self` uses the same subject as the inner type
+                        unsafe { <#inner_ty as Extend>::prop_data(&self.#member).cast() }
+                    },
+                    Some(inner_ty),
+                )),
+                None => Ok((quote! { &self.#member }, None)),
+            }
+        }
+        Data::Enum(data) => {
+            if data.variants.is_empty() {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "Extend can't be derived for enums without variants",
+                ));
+            }
+            let arms = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let name = &variant.ident;
+                    let (member, forward) = prop_data_member(&variant.fields, name.span())?;
+                    if let Some(inner_ty) = forward {
+                        return Err(syn::Error::new(
+                            inner_ty.span(),
+                            "#[prop_data(forward)] can't be used in enums",
+                        ));
+                    }
+                    Ok(quote! { Self::#name { #member: prop_data, .. } => prop_data })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            Ok((
+                quote! {
+                    match self {
+                        #(#arms,)*
+                    }
+                },
+                None,
+            ))
         }
-        Data::Enum(_) | Data::Union(_) => todo!(), // TODO: Error here
+        Data::Union(data) => Err(syn::Error::new(
+            data.union_token.span(),
+            "Extend can't be derived for unions",
+        )),
     }
 }
 
+/// Finds the field marked with `#[prop_data]` in a struct or enum variant, along with its type if
+/// it is marked with `#[prop_data(forward)]`. `span` is used for errors if there are no fields.
+fn prop_data_member(
+    fields: &Fields,
+    span: proc_macro2::Span,
+) -> syn::Result<(Member, Option<Type>)> {
+    let mut prop_data_fields = fields.iter().enumerate().filter_map(|(index, field)| {
+        let attr = field
+            .attrs
+            .iter()
+            .find(|attr| attr.path.is_ident("prop_data"))?;
+        Some((index, field, attr))
+    });
+    let span = match fields {
+        Fields::Unit => span,
+        _ => fields.span(),
+    };
+    let (index, field, attr) = match as_singleton(&mut prop_data_fields) {
+        Some(found) => found,
+        None => {
+            return Err(syn::Error::new(
+                span,
+                "Exactly one field must be marked with a #[prop_data] attribute",
+            ))
+        }
+    };
+    let member = match &field.ident {
+        Some(name) => Member::Named(name.clone()),
+        None => Member::Unnamed(Index {
+            index: index as u32,
+            span: field.span(),
+        }),
+    };
+    let forward = match attr.parse_meta()? {
+        Meta::Path(_) => false,
+        Meta::List(list) => match as_singleton(&mut list.nested.iter()) {
+            Some(NestedMeta::Meta(Meta::Path(path))) if path.is_ident("forward") => true,
+            _ => return Err(syn::Error::new(list.nested.span(), "Expected `forward`")),
+        },
+        meta => return Err(syn::Error::new(meta.span(), "Expected `forward`")),
+    };
+    Ok((member, forward.then(|| field.ty.clone())))
+}
+
 /// Determines whether an iterator has a single value, and if so, returns it.
 fn as_singleton<I: Iterator>(it: &mut I) -> Option<I::Item> {
     match it.next() {
@@ -77,122 +276,523 @@ fn as_singleton<I: Iterator>(it: &mut I) -> Option<I::Item> {
 }
 
 /// Rewrites a function to automatically memoize its result by storing it as a
-/// `Property` value. This requires the function to have exactly one argument, whost type must
-/// implement `Extend`.
+/// `Property` value. The first argument of the function must be a reference to a type which
+/// implements `Extend`.
+///
+/// The attribute can also be used on `&self` methods of types which implement `Extend`, in which
+/// case the companion items (see below) are associated functions and methods of the type. To
+/// memoize methods in a trait impl, the attribute must also be applied to the impl block itself,
+/// so that the companion items can be put in a separate inherent impl block.
+///
+/// Any additional arguments must implement `Hash`, `Eq` and `Clone`. The results for each object
+/// are then stored in a `MemoMap` keyed by a tuple of the additional arguments. The number of
+/// results stored per object can be limited using `capacity`, e.g. `#[memoize(capacity = 16)]`,
+/// in which case the least recently used result is evicted first. `share` can't be used with
+/// additional arguments.
 ///
 /// There are two possible modes of operation, specified using an argument to the attribute.
 /// `clone` (the default) will cause the rewritten function to return a [`clone`](Clone::clone) of
 /// the property value. `share` will cause the rewritten function to return an immutable reference
 /// to the property value.
 ///
+/// Since the rewritten function may be called from any thread, the property value must be `Send`
+/// and `Sync`.
+///
+/// The attribute also generates the following companion items, with the same visibility as the
+/// function (shown here for a function named `data`):
+///  * `data_property()`, which returns a `&'static Property` storing the memoized results.
+///  * `invalidate_data(obj: &mut T) -> bool`, which discards the memoized results for an object,
+///    so that they will be recomputed on the next call.
+///  * `is_cached_data(obj: &T, ..) -> bool`, which determines whether there is a memoized result
+///    for an object and the given additional arguments.
+///
 /// ```
 /// use dynprops::{Dynamic, memoize};
-/// use std::cell::Cell;
+/// use std::sync::atomic::{AtomicI32, Ordering};
 ///
 /// #[memoize(share)]
-/// fn data(context: &Dynamic) -> &Cell<i32> {
-///     Cell::new(0)
+/// fn data(context: &Dynamic) -> &AtomicI32 {
+///     AtomicI32::new(0)
 /// }
 ///
 /// let context = Dynamic::new();
-/// assert_eq!(data(&context).get(), 0);
-/// data(&context).set(9);
-/// assert_eq!(data(&context).get(), 9);
+/// assert_eq!(data(&context).load(Ordering::Relaxed), 0);
+/// data(&context).store(9, Ordering::Relaxed);
+/// assert_eq!(data(&context).load(Ordering::Relaxed), 9);
+///
+/// let mut context = context;
+/// assert!(is_cached_data(&context));
+/// assert!(invalidate_data(&mut context));
+/// assert!(!is_cached_data(&context));
+/// assert_eq!(data(&context).load(Ordering::Relaxed), 0);
+/// ```
+///
+/// The first argument must be a reference to an object:
+///
+/// ```compile_fail
+/// #[dynprops::memoize]
+/// fn answer() -> u32 {
+///     42
+/// }
+/// ```
+///
+/// Memoized methods must take `&self`:
+///
+/// ```compile_fail
+/// use dynprops::{memoize, Extend, PropertyData};
+///
+/// #[derive(Extend)]
+/// struct Counter(#[prop_data] PropertyData<Counter>);
+///
+/// impl Counter {
+///     #[memoize]
+///     fn count(&mut self) -> u32 {
+///         0
+///     }
+/// }
+/// ```
+///
+/// Memoized functions must return a value, and can't have type parameters:
+///
+/// ```compile_fail
+/// use dynprops::{memoize, Dynamic};
+///
+/// #[memoize]
+/// fn touch(context: &Dynamic) {}
+/// ```
+///
+/// ```compile_fail
+/// use dynprops::{memoize, Dynamic};
+///
+/// #[memoize]
+/// fn default<T: Default + Clone + Send + Sync + 'static>(context: &Dynamic) -> T {
+///     T::default()
+/// }
+/// ```
+///
+/// `share` requires a reference return type:
+///
+/// ```compile_fail
+/// use dynprops::{memoize, Dynamic};
+///
+/// #[memoize(share)]
+/// fn data(context: &Dynamic) -> u32 {
+///     0
+/// }
 /// ```
 #[proc_macro_attribute]
 pub fn memoize(args: TokenStream, input: TokenStream) -> TokenStream {
-    let opts = match parse_memoize_opts(parse_macro_input!(args)) {
-        Ok(opts) => opts,
-        Err(err) => {
-            return TokenStream::from(err.to_compile_error());
-        }
+    let args = parse_macro_input!(args as AttributeArgs);
+    let input = parse_macro_input!(input as Item);
+    let res = match input {
+        Item::Fn(input) => parse_memoize_opts(args)
+            .and_then(|opts| memoize_inner(opts, input, None))
+            .map(|res| {
+                let Memoized { item, companions } = res;
+                quote! { #item #companions }
+            }),
+        Item::Impl(input) => match args.first() {
+            Some(arg) => Err(syn::Error::new(
+                arg.span(),
+                "Arguments should be given to the #[memoize] attributes on methods",
+            )),
+            None => memoize_impl(input),
+        },
+        input => Err(syn::Error::new(
+            input.span(),
+            "#[memoize] can only be applied to functions, methods and impl blocks",
+        )),
     };
-    let input = parse_macro_input!(input as syn::ItemFn);
-    TokenStream::from(match memoize_inner(opts, input) {
+    TokenStream::from(match res {
         Ok(res) => res,
         Err(err) => err.to_compile_error(),
     })
 }
 
-fn memoize_inner(opts: MemoizeMode, input: ItemFn) -> syn::Result<TokenStream2> {
+/// The result of rewriting a memoized function.
+struct Memoized {
+    /// The rewritten function.
+    item: TokenStream2,
+
+    /// The companion items for the function.
+    companions: TokenStream2,
+}
+
+/// Rewrites the methods marked with `#[memoize]` in an impl block. For trait impls, the companion
+/// items are put in a separate inherent impl block, since they aren't members of the trait.
+fn memoize_impl(mut input: ItemImpl) -> syn::Result<TokenStream2> {
+    let companion_vis: Option<Visibility> = input.trait_.as_ref().map(|_| parse_quote! { pub });
+    let mut companions = Vec::new();
+    for item in input.items.iter_mut() {
+        let method = match item {
+            ImplItem::Method(method) => method,
+            _ => continue,
+        };
+        let index = method.attrs.iter().position(|attr| {
+            attr.path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "memoize")
+        });
+        let attr = match index {
+            Some(index) => method.attrs.remove(index),
+            None => continue,
+        };
+        let args = match attr.parse_meta()? {
+            Meta::Path(_) => Vec::new(),
+            Meta::List(list) => list.nested.into_iter().collect(),
+            meta => return Err(syn::Error::new(meta.span(), "Unexpect attribute argument")),
+        };
+        let input = ItemFn {
+            attrs: method.attrs.clone(),
+            vis: method.vis.clone(),
+            sig: method.sig.clone(),
+            block: Box::new(method.block.clone()),
+        };
+        let res = memoize_inner(parse_memoize_opts(args)?, input, companion_vis.clone())?;
+        *item = ImplItem::Verbatim(res.item);
+        companions.push(res.companions);
+    }
+    if input.trait_.is_some() {
+        let (impl_generics, _, where_clause) = input.generics.split_for_impl();
+        let self_ty = &input.self_ty;
+        Ok(quote! {
+            #input
+
+            impl #impl_generics #self_ty #where_clause {
+                #(#companions)*
+            }
+        })
+    } else {
+        input
+            .items
+            .extend(companions.into_iter().map(ImplItem::Verbatim));
+        Ok(quote! { #input })
+    }
+}
+
+/// Rewrites a memoized function. The companion items are given the visibility of the function,
+/// unless `companion_vis` is provided.
+fn memoize_inner(
+    opts: MemoizeOpts,
+    input: ItemFn,
+    companion_vis: Option<Visibility>,
+) -> syn::Result<Memoized> {
     let vis = &input.vis;
-    let sig = &input.sig;
+    let companion_vis = companion_vis.as_ref().unwrap_or(vis);
     let block = &input.block;
-    let arg = match as_singleton(&mut sig.inputs.iter()) {
-        Some(FnArg::Typed(arg)) => arg,
-        _ => todo!(), // TODO: Error here
+    let mut inputs = input.sig.inputs.iter();
+
+    // The object is either given by a reference argument, or by `&self` for methods
+    let (pat, arg_ty) =
+        match inputs.next() {
+            Some(FnArg::Typed(arg)) => match (&*arg.pat, &*arg.ty) {
+                (Pat::Ident(pat), Type::Reference(TypeReference { elem: ty, .. })) => {
+                    (Some(pat), quote! { #ty })
+                }
+                (Pat::Ident(_), ty) => {
+                    return Err(syn::Error::new(
+                        ty.span(),
+                        "Expected a reference to a type which implements `Extend`",
+                    ))
+                }
+                (pat, _) => {
+                    return Err(syn::Error::new(
+                        pat.span(),
+                        "Expected an identifier for the first argument of a memoized function",
+                    ))
+                }
+            },
+            Some(FnArg::Receiver(receiver)) => {
+                if receiver.reference.is_none() || receiver.mutability.is_some() {
+                    return Err(syn::Error::new(
+                        receiver.span(),
+                        "Memoized methods must take `&self`",
+                    ));
+                }
+                (None, quote! { Self })
+            }
+            None => return Err(syn::Error::new(
+                input.sig.paren_token.span,
+                "Memoized functions must take a reference to a type which implements `Extend` as \
+                their first argument",
+            )),
+        };
+    let res_ty = match &input.sig.output {
+        ReturnType::Type(_, ty) => &**ty,
+        ReturnType::Default => {
+            return Err(syn::Error::new(
+                input.sig.ident.span(),
+                "Memoized functions must return a value",
+            ))
+        }
     };
-    let pat = &arg.pat;
-    let arg_ty = match &*arg.ty {
-        Type::Reference(TypeReference { elem: ty, .. }) => &**ty,
-        _ => todo!(), // TODO: Error here
+    if let Some(param) = input.sig.generics.type_params().next() {
+        return Err(syn::Error::new(
+            param.span(),
+            "Memoized functions can't have type parameters",
+        ));
+    }
+
+    // Additional arguments are used as the key for a map of results, so they are renamed in the
+    // rewritten function in order to be used as values
+    let mut extra_pats = Vec::new();
+    let mut extra_tys = Vec::new();
+    for arg in inputs {
+        match arg {
+            FnArg::Typed(arg) => {
+                extra_pats.push(&*arg.pat);
+                extra_tys.push(&*arg.ty);
+            }
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new(
+                    receiver.span(),
+                    "`self` must be the first argument",
+                ))
+            }
+        }
+    }
+    let extra_args = (0..extra_pats.len())
+        .map(|i| format_ident!("arg_{}", i))
+        .collect::<Vec<_>>();
+    let mut sig = input.sig.clone();
+    let mut sig_inputs = sig.inputs.iter_mut();
+    if let (Some(pat), Some(FnArg::Typed(arg))) = (pat, sig_inputs.next()) {
+        let ident = &pat.ident;
+        arg.pat = parse_quote! { #ident };
+    }
+    for (arg, name) in sig_inputs.zip(extra_args.iter()) {
+        if let FnArg::Typed(arg) = arg {
+            arg.pat = parse_quote! { #name };
+        }
+    }
+
+    let value_ty = match opts.mode {
+        MemoizeMode::Clone => res_ty,
+        MemoizeMode::Share => match res_ty {
+            Type::Reference(TypeReference { elem, .. }) => &**elem,
+            _ => {
+                return Err(syn::Error::new(
+                    res_ty.span(),
+                    "Expected reference type when using `share`",
+                ))
+            }
+        },
     };
-    let res_ty = match &sig.output {
-        ReturnType::Type(_, ty) => &**ty,
-        _ => todo!(), // TODO: Error here
+    let name = &sig.ident;
+    let prop_name = format_ident!("{}_property", name);
+    let invalidate_name = format_ident!("invalidate_{}", name);
+    let is_cached_name = format_ident!("is_cached_{}", name);
+    let (obj, obj_param, obj_param_mut, prop) = match pat {
+        Some(_) => (
+            quote! { obj },
+            quote! { obj: &#arg_ty },
+            quote! { obj: &mut #arg_ty },
+            quote! { #prop_name() },
+        ),
+        None => (
+            quote! { self },
+            quote! { &self },
+            quote! { &mut self },
+            quote! { Self::#prop_name() },
+        ),
     };
-    match opts {
-        MemoizeMode::Clone => Ok(quote! {
-            #vis #sig {
-                static ONCE: ::std::sync::Once = ::std::sync::Once::new();
-                static mut PROP: *mut ::dynprops::Property<#arg_ty, #res_ty> =
-                    0 as *mut ::dynprops::Property<#arg_ty, #res_ty>;
-                let prop = unsafe {
-                    ONCE.call_once(|| {
-                        let prop = ::dynprops::Property::new();
-                        PROP = ::std::boxed::Box::into_raw(::std::boxed::Box::new(prop));
-                    });
-                    &*PROP
+    let compute_name = format_ident!("__memoize_compute_{}", name);
+    let mut compute = quote! {};
+    let (prop_ty, prop_init, get, is_cached_args, is_cached, invalidate) = if extra_args.is_empty()
+    {
+        if let Some(capacity) = &opts.capacity {
+            return Err(syn::Error::new(
+                capacity.span(),
+                "`capacity` requires the function to have additional arguments",
+            ));
+        }
+
+        // Methods get a hidden companion method to compute the value, since an initializer
+        // defined within the property's initializer can't refer to `self`
+        let (prop_init, value) = match pat {
+            Some(pat) => {
+                let ident = &pat.ident;
+                (
+                    quote! {
+                        #[allow(unused_variables)]
+                        fn init(#pat: &#arg_ty) -> #value_ty #block
+                        ::dynprops::Property::with_init(init)
+                    },
+                    quote! { #prop.get(#ident) },
+                )
+            }
+            None => {
+                compute = quote! {
+                    #[doc(hidden)]
+                    fn #compute_name(&self) -> #value_ty #block
                 };
-                <#res_ty as Clone>::clone(prop.get_with_init(#pat, || {
-                    #block
-                }))
+                (
+                    quote! {
+                        ::dynprops::Property::with_init(|obj: &Self| obj.#compute_name())
+                    },
+                    quote! { #prop.get(self) },
+                )
             }
-        }),
-        MemoizeMode::Share => {
-            let inner_ty = match &*res_ty {
-                Type::Reference(TypeReference { elem, .. }) => elem,
-                _ => {
-                    return Err(syn::Error::new(
-                        res_ty.span(),
-                        "Expected reference type when using `share`",
-                    ))
+        };
+        let get = match opts.mode {
+            MemoizeMode::Clone => quote! { <#res_ty as Clone>::clone(#value) },
+            MemoizeMode::Share => value,
+        };
+        (
+            quote! { #value_ty },
+            prop_init,
+            get,
+            quote! {},
+            quote! { #prop.is_set(#obj) },
+            quote! { #prop.discard(#obj) },
+        )
+    } else {
+        if let MemoizeMode::Share = opts.mode {
+            return Err(syn::Error::new(
+                res_ty.span(),
+                "`share` can't be used for functions with additional arguments",
+            ));
+        }
+        let capacity = match &opts.capacity {
+            Some(capacity) => quote! { ::std::option::Option::Some(#capacity) },
+            None => quote! { ::std::option::Option::None },
+        };
+        let key = quote! { (#(::std::clone::Clone::clone(&#extra_args),)*) };
+        let get = match pat {
+            Some(pat) => {
+                let ident = &pat.ident;
+                quote! {
+                    #[allow(unused_variables)]
+                    fn init(#pat: &#arg_ty, #(#extra_pats: #extra_tys),*) -> #res_ty #block
+                    #prop.get(#ident).get_or_insert_with(#key, || init(#ident, #(#extra_args),*))
                 }
-            };
-            Ok(quote! {
-                #vis #sig {
-                    static ONCE: ::std::sync::Once = ::std::sync::Once::new();
-                    static mut PROP: *mut ::dynprops::Property<#arg_ty, #inner_ty> =
-                        0 as *mut ::dynprops::Property<#arg_ty, #inner_ty>;
-                    let prop = unsafe {
-                        ONCE.call_once(|| {
-                            let prop = ::dynprops::Property::new();
-                            PROP = ::std::boxed::Box::into_raw(::std::boxed::Box::new(prop));
-                        });
-                        &*PROP
-                    };
-                    prop.get_with_init(#pat, || {
-                        #block
-                    })
+            }
+            None => quote! {
+                #prop.get(self).get_or_insert_with(#key, move || {
+                    #[allow(unused_variables)]
+                    let (#(#extra_pats,)*) = (#(#extra_args,)*);
+                    #block
+                })
+            },
+        };
+        (
+            quote! { ::dynprops::MemoMap<(#(#extra_tys,)*), #res_ty> },
+            quote! {
+                ::dynprops::Property::with_init(|_: &#arg_ty| ::dynprops::MemoMap::new(#capacity))
+            },
+            get,
+            quote! { #(, #extra_args: #extra_tys)* },
+            quote! {
+                match #prop.try_get(#obj) {
+                    Some(results) => results.contains_key(&(#(#extra_args,)*)),
+                    None => false,
                 }
-            })
-        }
-    }
+            },
+            quote! {
+                let is_cached = match #prop.try_get(#obj) {
+                    Some(results) => !results.is_empty(),
+                    None => false,
+                };
+                #prop.discard(#obj);
+                is_cached
+            },
+        )
+    };
+
+    // Free functions can store the property in a `static`, but methods may be in a generic impl,
+    // and a `static` can't depend on `Self`
+    let store = match pat {
+        Some(_) => quote! {
+            static PROP: ::std::sync::OnceLock<::dynprops::Property<#arg_ty, #prop_ty>> =
+                ::std::sync::OnceLock::new();
+            PROP.get_or_init(|| { #prop_init })
+        },
+        None => quote! {
+            static PROPS: ::dynprops::PropertyRegistry = ::dynprops::PropertyRegistry::new();
+            PROPS.get_or_init(|| { #prop_init })
+        },
+    };
+
+    // The property is exposed through a separate function so that it can be accessed by the
+    // companion items
+    let attrs = &input.attrs;
+    let prop_doc = format!(
+        "Gets the property which stores the memoized results of [`{}`].",
+        name
+    );
+    let invalidate_doc = format!(
+        "Discards the memoized results of [`{}`] for the given object, so that they will be \
+        recomputed on the next call. Returns `false` if there were no memoized results.",
+        name
+    );
+    let is_cached_doc = format!(
+        "Determines whether there is a memoized result of [`{}`] for the given arguments.",
+        name
+    );
+    Ok(Memoized {
+        item: quote! {
+            #(#attrs)*
+            #vis #sig {
+                #get
+            }
+        },
+        companions: quote! {
+            #compute
+
+            #[doc = #prop_doc]
+            #companion_vis fn #prop_name() -> &'static ::dynprops::Property<#arg_ty, #prop_ty> {
+                #store
+            }
+
+            #[doc = #invalidate_doc]
+            #companion_vis fn #invalidate_name(#obj_param_mut) -> bool {
+                #invalidate
+            }
+
+            #[doc = #is_cached_doc]
+            #companion_vis fn #is_cached_name(#obj_param #is_cached_args) -> bool {
+                #is_cached
+            }
+        },
+    })
 }
 
-fn parse_memoize_opts(args: AttributeArgs) -> syn::Result<MemoizeMode> {
-    let mut mode = MemoizeMode::Clone;
+fn parse_memoize_opts(args: AttributeArgs) -> syn::Result<MemoizeOpts> {
+    let mut opts = MemoizeOpts {
+        mode: MemoizeMode::Clone,
+        capacity: None,
+    };
     for arg in args {
         match arg {
             NestedMeta::Meta(Meta::Path(id)) => match id.get_ident() {
-                Some(id) if id == "clone" => mode = MemoizeMode::Clone,
-                Some(id) if id == "share" => mode = MemoizeMode::Share,
+                Some(id) if id == "clone" => opts.mode = MemoizeMode::Clone,
+                Some(id) if id == "share" => opts.mode = MemoizeMode::Share,
                 _ => return Err(syn::Error::new(id.span(), "Unexpect attribute argument")),
             },
+            NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                path,
+                lit: Lit::Int(lit),
+                ..
+            })) if path.is_ident("capacity") => {
+                if lit.base10_parse::<usize>()? == 0 {
+                    return Err(syn::Error::new(lit.span(), "Capacity must be non-zero"));
+                }
+                opts.capacity = Some(lit)
+            }
             _ => return Err(syn::Error::new(arg.span(), "Unexpect attribute argument")),
         }
     }
-    Ok(mode)
+    Ok(opts)
+}
+
+/// The options for the [`memoize`] attribute.
+struct MemoizeOpts {
+    mode: MemoizeMode,
+
+    /// The maximum number of results to store per object, for functions with additional
+    /// arguments.
+    capacity: Option<LitInt>,
 }
 
 /// The operation mode for the [`memoize`] attribute.
@@ -0,0 +1,271 @@
+//! Properties whose values are atomics, allowing them to be changed through a shared reference to
+//! the property.
+use crate::*;
+use std::sync::atomic::*;
+
+/// An atomic type which can be used as the value of an [`AtomicProperty`]. This is implemented for
+/// the atomic types in [`std::sync::atomic`].
+pub trait Atomic: Send + Sync + 'static {
+    /// The type of value stored in the atomic.
+    type Value: Copy;
+
+    /// Creates a new atomic with the given value.
+    fn new(value: Self::Value) -> Self;
+
+    /// Loads the value of the atomic.
+    fn load(&self, order: Ordering) -> Self::Value;
+
+    /// Stores a value in the atomic.
+    fn store(&self, value: Self::Value, order: Ordering);
+
+    /// Stores a value in the atomic, returning the previous value.
+    fn swap(&self, value: Self::Value, order: Ordering) -> Self::Value;
+
+    /// Stores `new` in the atomic if its value is `current`. Returns the previous value, as `Ok`
+    /// if it was `current` and `Err` otherwise.
+    fn compare_exchange(
+        &self,
+        current: Self::Value,
+        new: Self::Value,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Self::Value, Self::Value>;
+}
+
+/// An [`Atomic`] integer type.
+pub trait AtomicInteger: Atomic {
+    /// Adds to the value of the atomic (wrapping around on overflow), returning the previous
+    /// value.
+    fn fetch_add(&self, value: Self::Value, order: Ordering) -> Self::Value;
+
+    /// Subtracts from the value of the atomic (wrapping around on overflow), returning the
+    /// previous value.
+    fn fetch_sub(&self, value: Self::Value, order: Ordering) -> Self::Value;
+}
+
+macro_rules! impl_atomic {
+    ($($atomic:ty: $value:ty),* $(,)?) => {
+        $(
+            impl Atomic for $atomic {
+                type Value = $value;
+
+                fn new(value: $value) -> Self {
+                    <$atomic>::new(value)
+                }
+
+                fn load(&self, order: Ordering) -> $value {
+                    self.load(order)
+                }
+
+                fn store(&self, value: $value, order: Ordering) {
+                    self.store(value, order)
+                }
+
+                fn swap(&self, value: $value, order: Ordering) -> $value {
+                    self.swap(value, order)
+                }
+
+                fn compare_exchange(
+                    &self,
+                    current: $value,
+                    new: $value,
+                    success: Ordering,
+                    failure: Ordering,
+                ) -> Result<$value, $value> {
+                    self.compare_exchange(current, new, success, failure)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_atomic_integer {
+    ($($atomic:ty: $value:ty),* $(,)?) => {
+        impl_atomic!($($atomic: $value),*);
+        $(
+            impl AtomicInteger for $atomic {
+                fn fetch_add(&self, value: $value, order: Ordering) -> $value {
+                    self.fetch_add(value, order)
+                }
+
+                fn fetch_sub(&self, value: $value, order: Ordering) -> $value {
+                    self.fetch_sub(value, order)
+                }
+            }
+        )*
+    };
+}
+
+impl_atomic!(AtomicBool: bool);
+
+impl_atomic_integer!(
+    AtomicI8: i8,
+    AtomicI16: i16,
+    AtomicI32: i32,
+    AtomicI64: i64,
+    AtomicIsize: isize,
+    AtomicU8: u8,
+    AtomicU16: u16,
+    AtomicU32: u32,
+    AtomicU64: u64,
+    AtomicUsize: usize,
+);
+
+impl<V: 'static> Atomic for AtomicPtr<V> {
+    type Value = *mut V;
+
+    fn new(value: *mut V) -> Self {
+        AtomicPtr::new(value)
+    }
+
+    fn load(&self, order: Ordering) -> *mut V {
+        self.load(order)
+    }
+
+    fn store(&self, value: *mut V, order: Ordering) {
+        self.store(value, order)
+    }
+
+    fn swap(&self, value: *mut V, order: Ordering) -> *mut V {
+        self.swap(value, order)
+    }
+
+    fn compare_exchange(
+        &self,
+        current: *mut V,
+        new: *mut V,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<*mut V, *mut V> {
+        self.compare_exchange(current, new, success, failure)
+    }
+}
+
+/// A property whose values are [`Atomic`]s. Unlike [`Property`], values can be changed through a
+/// shared reference to the property, so it can be used concurrently by many threads. Values are
+/// stored directly in the object, and once a value is initialized, reading it doesn't require any
+/// locks. Changing a value only takes a lock if a [`ComputedProperty`] value has been computed on
+/// the object, to invalidate the values which depend on it.
+///
+/// Changes to values invalidate dependent [`ComputedProperty`] values, but they are not recorded
+/// by [`Savepoint`]s.
+///
+/// ## Example
+///
+/// ```
+/// use dynprops::{AtomicProperty, Dynamic};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// let hits = AtomicProperty::<Dynamic, AtomicU64>::new();
+/// let obj = Dynamic::new();
+/// std::thread::scope(|s| {
+///     for _ in 0..4 {
+///         s.spawn(|| hits.fetch_add(&obj, 1, Ordering::Relaxed));
+///     }
+/// });
+/// assert_eq!(hits.load(&obj, Ordering::Relaxed), 4);
+/// ```
+pub struct AtomicProperty<T: Extend, A> {
+    inner: Property<T, A>,
+}
+
+impl<T: Extend, A: Atomic> AtomicProperty<T, A>
+where
+    A::Value: Default,
+{
+    /// Creates a new property whose values are initialized to [`Default::default()`].
+    pub fn new() -> Self {
+        Self::with_init(|_| Default::default())
+    }
+}
+
+impl<T: Extend, A: Atomic> AtomicProperty<T, A> {
+    /// Creates a new property whose values are initialized to `value`.
+    pub fn with_const_init(value: A::Value) -> Self
+    where
+        A::Value: Send + Sync,
+    {
+        Self::with_init(move |_| value)
+    }
+
+    /// Creates a new property whose values are initialized by calling `init` on the object they
+    /// are for.
+    pub fn with_init(init: impl Fn(&T) -> A::Value + Send + Sync + 'static) -> Self {
+        AtomicProperty {
+            inner: Property::with_init(move |obj| A::new(init(obj))),
+        }
+    }
+
+    /// Gets the atomic for this property on the given object, initializing it if needed. This
+    /// can be used for operations not provided by [`AtomicProperty`].
+    pub fn get<'a>(&'a self, obj: &'a T) -> &'a A {
+        self.inner.get(obj)
+    }
+
+    /// Loads the value of this property on the given object.
+    pub fn load(&self, obj: &T, order: Ordering) -> A::Value {
+        self.inner.get(obj).load(order)
+    }
+
+    /// Stores a value for this property on the given object.
+    pub fn store(&self, obj: &T, value: A::Value, order: Ordering) {
+        // Invalidate after storing, so that computations which read the old value can't finish
+        // afterwards
+        self.inner.get(obj).store(value, order);
+        computed::invalidate(obj, &self.inner.info);
+    }
+
+    /// Stores a value for this property on the given object, returning the previous value.
+    pub fn swap(&self, obj: &T, value: A::Value, order: Ordering) -> A::Value {
+        let res = self.inner.get(obj).swap(value, order);
+        computed::invalidate(obj, &self.inner.info);
+        res
+    }
+
+    /// Stores `new` for this property on the given object if its value is `current`. Returns the
+    /// previous value, as `Ok` if it was `current` and `Err` otherwise.
+    pub fn compare_exchange(
+        &self,
+        obj: &T,
+        current: A::Value,
+        new: A::Value,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<A::Value, A::Value> {
+        let res = self
+            .inner
+            .get(obj)
+            .compare_exchange(current, new, success, failure);
+        if res.is_ok() {
+            computed::invalidate(obj, &self.inner.info);
+        }
+        res
+    }
+}
+
+impl<T: Extend, A: AtomicInteger> AtomicProperty<T, A> {
+    /// Adds to the value of this property on the given object (wrapping around on overflow),
+    /// returning the previous value.
+    pub fn fetch_add(&self, obj: &T, value: A::Value, order: Ordering) -> A::Value {
+        let res = self.inner.get(obj).fetch_add(value, order);
+        computed::invalidate(obj, &self.inner.info);
+        res
+    }
+
+    /// Subtracts from the value of this property on the given object (wrapping around on
+    /// overflow), returning the previous value.
+    pub fn fetch_sub(&self, obj: &T, value: A::Value, order: Ordering) -> A::Value {
+        let res = self.inner.get(obj).fetch_sub(value, order);
+        computed::invalidate(obj, &self.inner.info);
+        res
+    }
+}
+
+impl<T: Extend, A: Atomic> Default for AtomicProperty<T, A>
+where
+    A::Value: Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,264 @@
+//! Computed properties, which cache a value derived from other properties of an object and
+//! recompute it when those properties change.
+use crate::*;
+use std::cell::RefCell;
+use std::sync::atomic::AtomicBool;
+
+/// Identifies the slot of a property within the objects of a [`Subject`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct SlotKey {
+    chunk_id: usize,
+    init_bit_offset: usize,
+}
+
+impl SlotKey {
+    fn of(info: &PropertyInfo) -> Self {
+        SlotKey {
+            chunk_id: info.chunk_id,
+            init_bit_offset: info.init_bit_offset,
+        }
+    }
+}
+
+/// Records that a cached value on an object depends on the value of a property of the same
+/// object.
+struct Dependency {
+    input: SlotKey,
+
+    /// The slot of the [`ComputedProperty`] whose value depends on `input`.
+    owner: SlotKey,
+
+    /// Cleared when the cached value is invalidated.
+    valid: Arc<AtomicBool>,
+}
+
+/// The value of the hidden property which stores the [`Dependency`]s of an object.
+type DependencyList = Mutex<Vec<Dependency>>;
+
+/// A computation of a [`ComputedProperty`] value which is in progress on the current thread.
+struct Frame {
+    data: *const RawPropertyData,
+    owner: SlotKey,
+    valid: Arc<AtomicBool>,
+
+    /// The properties which have been recorded as inputs of the computation so far.
+    inputs: Vec<SlotKey>,
+}
+
+thread_local! {
+    static FRAMES: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Removes the top [`Frame`] when a computation completes, even if it panics.
+struct FrameGuard;
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        FRAMES.with(|frames| frames.borrow_mut().pop());
+    }
+}
+
+impl Subject {
+    /// Gets the hidden property used to store the [`Dependency`]s of objects of this subject.
+    fn dependencies(&'static self) -> &'static PropertyInfo {
+        self.dependencies
+            .get_or_init(|| self.alloc_prop(&PropertyType::hidden::<DependencyList>()))
+    }
+}
+
+/// Records a read of a property on an object, if it occurs while computing the value of a
+/// [`ComputedProperty`] on the same object.
+pub(crate) fn track_read<T: Extend>(obj: &T, info: &PropertyInfo) {
+    let data = &obj.prop_data().source;
+    let dependency = FRAMES.with(|frames| {
+        let mut frames = frames.borrow_mut();
+        let frame = frames.last_mut()?;
+        let input = SlotKey::of(info);
+        if !ptr::eq(frame.data, data) || frame.inputs.contains(&input) {
+            return None;
+        }
+        frame.inputs.push(input);
+        Some(Dependency {
+            input,
+            owner: frame.owner,
+            valid: frame.valid.clone(),
+        })
+    });
+    if let Some(dependency) = dependency {
+        let list =
+            unsafe { data.get::<DependencyList>(info.subject.dependencies(), Default::default) };
+        lock_ignore_poison(list).push(dependency);
+    }
+}
+
+/// Gets the number of [`Dependency`]s recorded for an object.
+#[cfg(test)]
+pub(crate) fn num_dependencies<T: Extend>(obj: &T) -> usize {
+    let list: Option<&DependencyList> = T::subject()
+        .dependencies
+        .get()
+        .and_then(|info| unsafe { obj.prop_data().source.try_get(info) });
+    list.map_or(0, |list| lock_ignore_poison(list).len())
+}
+
+/// Invalidates all cached values on an object which depend on the given property, because it is
+/// about to be changed.
+pub(crate) fn invalidate<T: Extend>(obj: &T, info: &PropertyInfo) {
+    let dependencies = match info.subject.dependencies.get() {
+        Some(dependencies) => dependencies,
+        None => return,
+    };
+    let list = match unsafe {
+        obj.prop_data()
+            .source
+            .try_get::<DependencyList>(dependencies)
+    } {
+        Some(list) => list,
+        None => return,
+    };
+
+    // Invalidating a cached value also invalidates the values which depend on it
+    let mut list = lock_ignore_poison(list);
+    let mut pending = vec![SlotKey::of(info)];
+    while let Some(key) = pending.pop() {
+        list.retain(|dependency| {
+            if !dependency.valid.load(Ordering::Acquire) {
+                false
+            } else if dependency.input == key {
+                dependency.valid.store(false, Ordering::Release);
+                pending.push(dependency.owner);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// A cached value of a [`ComputedProperty`].
+struct Cached<P> {
+    value: Arc<P>,
+    valid: Arc<AtomicBool>,
+}
+
+/// A property whose value is computed from an object (typically from other properties of the
+/// object), and cached until any of the [`Property`]s it read on the object are changed.
+///
+/// Values are returned as an [`Arc`], since a value may be invalidated while it is being used.
+/// The computation is given when the property is created, so the properties it reads are usually
+/// shared with it, e.g. through an [`Arc`].
+///
+/// ## Example
+///
+/// ```
+/// use dynprops::{AtomicProperty, ComputedProperty, Dynamic};
+/// use std::sync::atomic::{AtomicU32, Ordering::Relaxed};
+/// use std::sync::Arc;
+///
+/// let width = Arc::new(AtomicProperty::<Dynamic, AtomicU32>::new());
+/// let height = Arc::new(AtomicProperty::<Dynamic, AtomicU32>::new());
+/// let area = ComputedProperty::new({
+///     let (width, height) = (width.clone(), height.clone());
+///     move |obj| width.load(obj, Relaxed) * height.load(obj, Relaxed)
+/// });
+/// let obj = Dynamic::new();
+/// width.store(&obj, 3, Relaxed);
+/// height.store(&obj, 4, Relaxed);
+/// assert_eq!(*area.get(&obj), 12);
+/// assert!(area.is_cached(&obj));
+/// width.store(&obj, 5, Relaxed);
+/// assert!(!area.is_cached(&obj));
+/// assert_eq!(*area.get(&obj), 20);
+/// ```
+pub struct ComputedProperty<T: Extend, P> {
+    cache: Property<T, Mutex<Option<Cached<P>>>>,
+    compute: Box<dyn Fn(&T) -> P + Send + Sync>,
+}
+
+impl<T: Extend, P: Send + Sync + 'static> ComputedProperty<T, P> {
+    /// Creates a new computed property whose values are computed by calling `compute` on the
+    /// object they are for. Reads of [`Property`]s on the object during `compute` are recorded,
+    /// and the value will be recomputed after any of those properties is set or mutably borrowed.
+    /// Only reads of properties on the object the value is being computed for are tracked.
+    pub fn new(compute: impl Fn(&T) -> P + Send + Sync + 'static) -> Self {
+        ComputedProperty {
+            cache: Property::new(),
+            compute: Box::new(compute),
+        }
+    }
+}
+
+impl<T: Extend, P: Send + Sync> ComputedProperty<T, P> {
+    /// Gets the value of this property on the given object, computing it if there is no valid
+    /// cached value. The value is computed at most once at a time for each object; other threads
+    /// wait for it to complete.
+    pub fn get(&self, obj: &T) -> Arc<P> {
+        let data: *const RawPropertyData = &obj.prop_data().source;
+        let owner = SlotKey::of(&self.cache.info);
+        let is_recursive = FRAMES.with(|frames| {
+            let frames = frames.borrow();
+            frames
+                .iter()
+                .any(|frame| ptr::eq(frame.data, data) && frame.owner == owner)
+        });
+        if is_recursive {
+            panic!("Computed property value depends on itself");
+        }
+
+        // Reading the cache records this value as a dependency of any value being computed
+        let mut cache = lock_ignore_poison(self.cache.get(obj));
+        if let Some(cached) = &*cache {
+            if cached.valid.load(Ordering::Acquire) {
+                return cached.value.clone();
+            }
+        }
+        let valid = Arc::new(AtomicBool::new(true));
+        FRAMES.with(|frames| {
+            frames.borrow_mut().push(Frame {
+                data,
+                owner,
+                valid: valid.clone(),
+                inputs: Vec::new(),
+            })
+        });
+        let guard = FrameGuard;
+        let value = Arc::new((self.compute)(obj));
+        drop(guard);
+
+        // If an input changed during the computation, `valid` will have been cleared, and the
+        // value will be recomputed on the next access
+        *cache = Some(Cached {
+            value: value.clone(),
+            valid,
+        });
+        value
+    }
+
+    /// Determines whether there is a valid cached value for this property on the given object.
+    pub fn is_cached(&self, obj: &T) -> bool {
+        match unsafe { obj.prop_data().source.try_get(&self.cache.info) } {
+            Some(cache) => {
+                let cache: &Mutex<Option<Cached<P>>> = cache;
+                match &*lock_ignore_poison(cache) {
+                    Some(cached) => cached.valid.load(Ordering::Acquire),
+                    None => false,
+                }
+            }
+            None => false,
+        }
+    }
+
+    /// Discards the cached value of this property on the given object, if any, so that it will
+    /// be recomputed on the next access. Values which depend on this property are also
+    /// invalidated.
+    pub fn invalidate(&self, obj: &T) {
+        let cache: Option<&Mutex<Option<Cached<P>>>> =
+            unsafe { obj.prop_data().source.try_get(&self.cache.info) };
+        if let Some(cache) = cache {
+            if let Some(cached) = lock_ignore_poison(cache).take() {
+                cached.valid.store(false, Ordering::Release);
+            }
+        }
+        invalidate(obj, &self.cache.info);
+    }
+}
@@ -0,0 +1,282 @@
+//! Properties for types which don't implement [`Extend`], whose values are stored in a side table
+//! keyed by the identity of the object.
+use crate::*;
+use std::sync::Weak;
+
+/// A reference which identifies an object for an [`ExternalProperty`]. This is implemented for
+/// `&'static T`, which is identified by its address, and for `&Arc<T>`, which is identified by
+/// the [`Arc`] allocation. Values for an [`Arc`] are removed some time after it is dropped.
+///
+/// Zero-sized `'static` values may share an address, and so share property values. Other kinds of
+/// references aren't supported: the reference counts of an `Rc` can't be updated from the other
+/// threads which share the property, and there is no way to tell when a pinned object is dropped,
+/// so a new object at the same address would see its values.
+pub trait ExternalKey<'a, T: ?Sized> {
+    /// Gets the object this reference is for.
+    fn object(&self) -> &'a T;
+
+    /// Gets the weak reference used to determine whether the object is still alive, or [`None`]
+    /// if it lives forever.
+    #[doc(hidden)]
+    fn owner(&self) -> Option<Weak<T>>;
+}
+
+impl<'a, T: ?Sized> ExternalKey<'a, T> for &'static T {
+    fn object(&self) -> &'a T {
+        self
+    }
+
+    fn owner(&self) -> Option<Weak<T>> {
+        None
+    }
+}
+
+impl<'a, T: ?Sized> ExternalKey<'a, T> for &'a Arc<T> {
+    fn object(&self) -> &'a T {
+        self
+    }
+
+    fn owner(&self) -> Option<Weak<T>> {
+        Some(Arc::downgrade(self))
+    }
+}
+
+/// The value of an [`ExternalProperty`] for one object.
+struct Entry<T: ?Sized, P> {
+    owner: Option<Weak<T>>,
+
+    /// The value is boxed so that it doesn't move when the table is resized.
+    value: Box<P>,
+}
+
+impl<T: ?Sized, P> Entry<T, P> {
+    /// Determines whether this entry is for an object which has been dropped.
+    fn is_dead(&self) -> bool {
+        self.owner
+            .as_ref()
+            .is_some_and(|owner| owner.strong_count() == 0)
+    }
+}
+
+struct ExternalState<T: ?Sized, P> {
+    entries: HashMap<usize, Entry<T, P>>,
+
+    /// The number of entries after entries for dropped objects were last removed.
+    purged_len: usize,
+}
+
+/// A property which can be used on types that don't implement [`Extend`], such as types from
+/// other crates. Rather than storing values in the object, values are stored in a table in the
+/// property, keyed by the identity of the object (see [`ExternalKey`]). This is slower than
+/// [`Property`], since every access looks up the object in the table.
+///
+/// ## Example
+///
+/// ```
+/// use dynprops::ExternalProperty;
+/// use std::sync::Arc;
+///
+/// static ORIGIN: (i32, i32) = (0, 0);
+/// let mut label = ExternalProperty::<(i32, i32), &str>::new();
+/// let point = Arc::new((1, 2));
+/// label.set(&ORIGIN, "Origin");
+/// label.set(&point, "Point");
+/// assert_eq!(*label.get(&ORIGIN), "Origin");
+/// assert_eq!(*label.get(&point), "Point");
+/// assert_eq!(*label.get(&Arc::new((1, 2))), "");
+/// ```
+pub struct ExternalProperty<T: ?Sized, P> {
+    state: Mutex<ExternalState<T, P>>,
+    init: Box<dyn Fn(&T) -> P + Send + Sync>,
+    _value: PhantomData<P>,
+}
+
+impl<T: ?Sized, P: Send + Default + 'static> ExternalProperty<T, P> {
+    /// Creates a new property whose values are initialized to [`Default::default()`].
+    pub fn new() -> Self {
+        Self::with_init(|_| Default::default())
+    }
+}
+
+impl<T: ?Sized, P: Send + Sync + Clone + 'static> ExternalProperty<T, P> {
+    /// Creates a new property whose values are initialized to a copy of `value`.
+    pub fn with_const_init(value: P) -> Self {
+        Self::with_init(move |_| value.clone())
+    }
+}
+
+impl<T: ?Sized, P: Send> ExternalProperty<T, P> {
+    /// Creates a new property whose values are initialized by calling `init` on the object they
+    /// are for.
+    pub fn with_init(init: impl Fn(&T) -> P + Send + Sync + 'static) -> Self {
+        ExternalProperty {
+            state: Mutex::new(ExternalState {
+                entries: HashMap::new(),
+                purged_len: 0,
+            }),
+            init: Box::new(init),
+            _value: PhantomData,
+        }
+    }
+
+    /// Gets the value of this property on the given object. If the property has never been
+    /// accessed before, it's value will be initialized using the property's initializer.
+    pub fn get<'a>(&'a self, obj: impl ExternalKey<'a, T>) -> &'a P {
+        self.get_with_init(obj, |obj| (self.init)(obj))
+    }
+
+    /// Gets the value of this property on the given object. If the property has never been
+    /// accessed before, it's value will be initialized using `init`.
+    pub fn get_with_init<'a>(
+        &'a self,
+        obj: impl ExternalKey<'a, T>,
+        init: impl FnOnce(&T) -> P,
+    ) -> &'a P {
+        if let Some(value) = self.try_get_key(&obj) {
+            return value;
+        }
+
+        // Don't hold the lock while initializing, since the initializer may access the property
+        let value = Box::new(init(obj.object()));
+        let mut state = lock_ignore_poison(&self.state);
+        let address = address_of(obj.object());
+        if let Some(entry) = state.entries.get(&address) {
+            if !entry.is_dead() {
+                return unsafe { &*(&*entry.value as *const P) };
+            }
+        }
+
+        // Values for dropped objects can't be borrowed, since references to values don't outlive
+        // the reference to the object they were obtained through
+        let mut dead = Self::purge_state(&mut state, false);
+        let entry = Entry {
+            owner: obj.owner(),
+            value,
+        };
+        let value: *const P = &*entry.value;
+        dead.extend(state.entries.insert(address, entry));
+        drop(state);
+        drop(dead);
+        unsafe { &*value }
+    }
+
+    /// Gets the value of this property on the given object, or [`None`] if it has not been
+    /// initialized.
+    pub fn try_get<'a>(&'a self, obj: impl ExternalKey<'a, T>) -> Option<&'a P> {
+        self.try_get_key(&obj)
+    }
+
+    /// Determines whether this property has been initialized on the given object.
+    pub fn is_set<'a>(&'a self, obj: impl ExternalKey<'a, T>) -> bool {
+        self.try_get_key(&obj).is_some()
+    }
+
+    fn try_get_key<'a>(&'a self, obj: &impl ExternalKey<'a, T>) -> Option<&'a P> {
+        let state = lock_ignore_poison(&self.state);
+        let entry = state.entries.get(&address_of(obj.object()))?;
+        if entry.is_dead() {
+            return None;
+        }
+
+        // Values are only removed from the table through a mutable reference to the property, or
+        // after their object is dropped, and they are boxed, so they don't move when the table is
+        // resized
+        Some(unsafe { &*(&*entry.value as *const P) })
+    }
+
+    /// Gets a mutable reference to the value of this property on the given object. If the
+    /// property has never been accessed before, it's value will be initialized using the
+    /// property's initializer.
+    pub fn get_mut<'a>(&'a mut self, obj: impl ExternalKey<'a, T>) -> &'a mut P {
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+        Self::purge_state(state, false);
+        let init = &self.init;
+        let entry = state
+            .entries
+            .entry(address_of(obj.object()))
+            .and_modify(|entry| {
+                if entry.is_dead() {
+                    *entry = Entry {
+                        owner: obj.owner(),
+                        value: Box::new(init(obj.object())),
+                    };
+                }
+            })
+            .or_insert_with(|| Entry {
+                owner: obj.owner(),
+                value: Box::new(init(obj.object())),
+            });
+        &mut entry.value
+    }
+
+    /// Sets the value of this property on the given object.
+    pub fn set<'a>(&'a mut self, obj: impl ExternalKey<'a, T>, value: P) {
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+        Self::purge_state(state, false);
+        let entry = Entry {
+            owner: obj.owner(),
+            value: Box::new(value),
+        };
+        state.entries.insert(address_of(obj.object()), entry);
+    }
+
+    /// Removes the value of this property from the given object, returning it if it was
+    /// initialized. The property will be reinitialized the next time it is accessed.
+    pub fn take<'a>(&'a mut self, obj: impl ExternalKey<'a, T>) -> Option<P> {
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+        Self::purge_state(state, false);
+        let entry = state.entries.remove(&address_of(obj.object()))?;
+        if entry.is_dead() {
+            return None;
+        }
+        Some(*entry.value)
+    }
+
+    /// Drops the value of this property on the given object, if it was initialized. The property
+    /// will be reinitialized the next time it is accessed.
+    pub fn unset<'a>(&'a mut self, obj: impl ExternalKey<'a, T>) {
+        drop(self.take(obj))
+    }
+
+    /// Drops the values of this property for objects which have been dropped. This is also done
+    /// periodically as values are set.
+    pub fn purge(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+        Self::purge_state(state, true);
+    }
+
+    /// Removes the entries for dropped objects if `force` is set or the table has doubled in size
+    /// since it was last purged. The removed entries are returned so that they can be dropped
+    /// after the state is unlocked, since dropping a value may access the property.
+    fn purge_state(state: &mut ExternalState<T, P>, force: bool) -> Vec<Entry<T, P>> {
+        if !force && state.entries.len() < max(state.purged_len * 2, MIN_PURGE_LEN) {
+            return Vec::new();
+        }
+        let addresses: Vec<usize> = state
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_dead())
+            .map(|(address, _)| *address)
+            .collect();
+        let dead = addresses
+            .iter()
+            .filter_map(|address| state.entries.remove(address))
+            .collect();
+        state.purged_len = state.entries.len();
+        dead
+    }
+}
+
+impl<T: ?Sized, P: Send + Default + 'static> Default for ExternalProperty<T, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The minimum number of entries before entries for dropped objects are removed automatically.
+const MIN_PURGE_LEN: usize = 16;
+
+/// Gets the address which identifies an object.
+fn address_of<T: ?Sized>(obj: &T) -> usize {
+    obj as *const T as *const () as usize
+}
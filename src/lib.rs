@@ -6,7 +6,8 @@
 //! use dynprops::*;
 //!
 //! // Define a type that can be extended with dynamic properties. To automatically derive Extend,
-//! // the type must be a struct with exactly one PropertyData field marked with #[prop_data]
+//! // the type must be a struct (or an enum, for each variant) with exactly one PropertyData field
+//! // marked with #[prop_data]
 //! #[derive(Extend)]
 //! struct Thing { #[prop_data] prop_data: PropertyData<Thing> }
 //!
@@ -26,16 +27,62 @@
 #[cfg(test)]
 mod tests;
 
+mod atomic;
+mod computed;
+mod external;
+mod memo;
+mod observe;
+mod savepoint;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod shared;
+mod sync;
+mod thread_local;
+
+pub use atomic::{Atomic, AtomicInteger, AtomicProperty};
+pub use computed::ComputedProperty;
+pub use external::{ExternalKey, ExternalProperty};
+pub use memo::MemoMap;
+#[doc(hidden)]
+pub use memo::PropertyRegistry;
+pub use observe::ObserverId;
+pub use savepoint::{RollbackError, Savepoint};
+pub use shared::{ExtendShared, SharedProperty};
+pub use sync::SyncProperty;
+pub use thread_local::{ThreadLocalProperty, ThreadLocalRef};
+
+#[cfg(doctest)]
+#[doc = include_str!("../README.md")]
+struct ReadmeDoctests;
+
 extern crate self as dynprops;
 pub use dynprops_derive::*;
+use observe::Observers;
+use savepoint::Journal;
 use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::any::TypeId;
 use std::cmp::max;
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut, Range};
 use std::ptr::NonNull;
-use std::sync::{Arc, Mutex};
-use std::{mem, ptr, usize};
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread::{self, ThreadId};
+use std::{fmt, mem, ptr};
 
 /// Types which can store values for arbitrary [`Property`]s.
+///
+/// # Safety
+///
+/// [`Extend::subject`] must always return the same [`Subject`]. Property values are stored
+/// according to a layout determined by the subject, so sharing [`PropertyData`] between subjects
+/// would cause values to be misinterpreted. Multiple types may use the same subject, but then the
+/// [`PropertyData`] returned by [`Extend::prop_data`] must never be accessed through a type which
+/// uses a different subject.
+///
+/// The [`PropertyData`] must also be owned by the object, so that exclusive access to the object
+/// implies exclusive access to its [`PropertyData`].
 pub unsafe trait Extend {
     /// Gets the [`Subject`] which identifies which [`Property`]s apply to values of this type.
     /// This must return the same subject every time it is called.
@@ -48,6 +95,9 @@ pub unsafe trait Extend {
 /// Identifies a category of objects and a dynamic set of [`Property`]s that apply to those objects.
 pub struct Subject {
     info: Mutex<SubjectInfo>,
+
+    /// A hidden property used to track the dependencies of [`ComputedProperty`] values.
+    dependencies: OnceLock<PropertyInfo>,
 }
 
 struct SubjectInfo {
@@ -58,22 +108,287 @@ struct SubjectInfo {
 struct ChunkInfo {
     id: usize,
     layout: Layout,
-    in_use_init_bits: usize,
+
+    /// The number of initialization words at the start of every instance of this chunk.
+    init_words: usize,
+    in_use_init_bits: Vec<usize>,
     in_use_size: usize,
-    drop_props: Vec<DropPropertyInfo>,
+
+    /// The ranges below `in_use_size` which were freed by dropped properties and can be reused.
+    /// These are sorted and never adjacent to each other.
+    free_ranges: Vec<Range<usize>>,
+
+    /// The properties which are currently allocated in this chunk.
+    props: Vec<SlotInfo>,
+
+    /// The addresses of all live instances of this chunk. This is needed so that values can be
+    /// dropped when their property is dropped.
+    instances: HashSet<usize>,
+
+    /// The number of [`ChunkPin`]s for this chunk. While this is non-zero, values are being read
+    /// without holding the chunk info lock, so dropped properties are added to `pending_free`
+    /// rather than freed immediately.
+    pins: usize,
+    pending_free: Vec<PendingFree>,
+}
+
+/// A slot of a dropped property which can't be freed until its chunk is no longer pinned.
+struct PendingFree {
+    offset: usize,
+    size: usize,
+    init_bit_offset: usize,
+    drop: Option<unsafe fn(NonNull<u8>)>,
 }
 
-struct DropPropertyInfo {
+/// Describes a property allocated in a chunk, for operations which apply to every property in the
+/// chunk.
+struct SlotInfo {
+    /// Uniquely identifies the property, since a slot may be reused after a property is dropped.
+    id: u64,
     offset: usize,
     init_bit_offset: usize,
-    drop: unsafe fn(NonNull<u8>),
+    value: ValueType,
+    storage: Storage,
+    drop: Option<unsafe fn(NonNull<u8>)>,
+    clone: Option<unsafe fn(NonNull<u8>, NonNull<u8>)>,
+
+    /// The name of the property, if it was given one.
+    name: Option<Arc<str>>,
+
+    /// Formats a value of the property, given a pointer to it. This is only available for named
+    /// properties.
+    debug: Option<DebugFn>,
+
+    /// Information for serializing values of the property, if it was registered with a key.
+    #[cfg(feature = "serde")]
+    serde: Option<serde_impl::SerdeInfo>,
+
+    /// Whether the property is used internally, and so shouldn't be listed by
+    /// [`Subject::properties`].
+    hidden: bool,
+}
+
+impl SlotInfo {
+    /// Describes the property using this slot.
+    fn descriptor(&self, chunk_id: usize) -> PropertyDescriptor {
+        PropertyDescriptor {
+            name: self.name.clone(),
+            type_id: self.value.type_id,
+            type_name: self.value.type_name,
+            size: self.value.size,
+            align: self.value.align,
+            needs_drop: self.value.needs_drop,
+            chunk: chunk_id,
+            offset: self.offset,
+        }
+    }
 }
 
+type DebugFn = unsafe fn(NonNull<u8>, &mut fmt::Formatter) -> fmt::Result;
+
+#[derive(Clone)]
 struct PropertyInfo {
+    /// The subject the property belongs to.
+    subject: &'static Subject,
+    slot_id: u64,
     chunk_id: usize,
     chunk: Arc<Mutex<ChunkInfo>>,
     offset: usize,
+    size: usize,
     init_bit_offset: usize,
+    storage: Storage,
+}
+
+impl PropertyInfo {
+    /// Describes this property, or returns [`None`] if it has been dropped.
+    fn descriptor(&self) -> Option<PropertyDescriptor> {
+        let chunk = lock_ignore_poison(&self.chunk);
+        let slot = chunk.props.iter().find(|slot| slot.id == self.slot_id)?;
+        Some(slot.descriptor(self.chunk_id))
+    }
+}
+
+/// Describes how the values of a property are stored in a chunk.
+#[derive(Clone, Copy)]
+enum Storage {
+    /// The value is stored directly in the chunk.
+    Direct,
+
+    /// The value is too large to be stored in the chunk directly. Instead, the chunk stores a
+    /// pointer to a separate (boxed) allocation for the value.
+    Boxed,
+
+    /// The chunk stores a pointer to a reference-counted allocation for the value, which may be
+    /// shared with clones of the object. The given function is used to ensure the allocation is
+    /// unique before it is mutated.
+    Shared(unsafe fn(NonNull<u8>)),
+}
+
+/// Describes the type of the values of a property.
+#[derive(Clone, Copy)]
+struct ValueType {
+    type_id: TypeId,
+    type_name: &'static str,
+    size: usize,
+    align: usize,
+    needs_drop: bool,
+}
+
+impl ValueType {
+    fn of<P: 'static>() -> Self {
+        ValueType {
+            type_id: TypeId::of::<P>(),
+            type_name: std::any::type_name::<P>(),
+            size: mem::size_of::<P>(),
+            align: mem::align_of::<P>(),
+            needs_drop: mem::needs_drop::<P>(),
+        }
+    }
+}
+
+/// Describes the values of a property, for the purpose of allocating space for them.
+struct PropertyType {
+    value: ValueType,
+    size: usize,
+    align: usize,
+    storage: Storage,
+    drop: Option<unsafe fn(NonNull<u8>)>,
+    clone: Option<unsafe fn(NonNull<u8>, NonNull<u8>)>,
+    hidden: bool,
+}
+
+impl PropertyType {
+    /// Describes a property whose values are of type `P`, which are reset when cloned.
+    fn new<P: 'static>() -> Self {
+        let value = ValueType::of::<P>();
+        if value.size > MAX_DIRECT_SIZE || value.align > CHUNK_ALIGN {
+            PropertyType {
+                value,
+                size: mem::size_of::<NonNull<P>>(),
+                align: mem::align_of::<NonNull<P>>(),
+                storage: Storage::Boxed,
+                drop: Some(slot::drop_boxed::<P>),
+                clone: None,
+                hidden: false,
+            }
+        } else {
+            PropertyType {
+                value,
+                size: value.size,
+                align: value.align,
+                storage: Storage::Direct,
+                drop: if value.needs_drop {
+                    Some(slot::drop_direct::<P>)
+                } else {
+                    None
+                },
+                clone: None,
+                hidden: false,
+            }
+        }
+    }
+
+    /// Describes a property whose values are of type `P`, with the given [`ClonePolicy`].
+    fn with_clone_policy<P: Clone + 'static>(policy: ClonePolicy) -> Self {
+        let mut res = Self::new::<P>();
+        match policy {
+            ClonePolicy::Clone => {
+                res.clone = Some(match res.storage {
+                    Storage::Direct => slot::clone_direct::<P>,
+                    _ => slot::clone_boxed::<P>,
+                })
+            }
+            ClonePolicy::Reset => (),
+            ClonePolicy::Share => {
+                res.size = mem::size_of::<NonNull<P>>();
+                res.align = mem::align_of::<NonNull<P>>();
+                res.storage = Storage::Shared(slot::make_unique_shared::<P>);
+                res.drop = Some(slot::drop_shared::<P>);
+                res.clone = Some(slot::clone_shared::<P>);
+            }
+        }
+        res
+    }
+
+    /// Describes a hidden property whose values are of type `P`, which is used internally and
+    /// isn't listed by [`Subject::properties`].
+    fn hidden<P: 'static>() -> Self {
+        PropertyType {
+            hidden: true,
+            ..Self::new::<P>()
+        }
+    }
+}
+
+/// Functions for manipulating property values stored in chunks, according to their [`Storage`].
+mod slot {
+    use super::Storage;
+    use std::fmt::{self, Debug, Formatter};
+    use std::ptr::{self, NonNull};
+    use std::sync::Arc;
+
+    pub unsafe fn drop_direct<P>(slot: NonNull<u8>) {
+        ptr::drop_in_place(slot.cast::<P>().as_ptr());
+    }
+
+    pub unsafe fn drop_boxed<P>(slot: NonNull<u8>) {
+        drop(Box::from_raw(read_ptr::<P>(slot).as_ptr()));
+    }
+
+    pub unsafe fn drop_shared<P>(slot: NonNull<u8>) {
+        drop(Arc::from_raw(read_ptr::<P>(slot).as_ptr()));
+    }
+
+    pub unsafe fn clone_direct<P: Clone>(src: NonNull<u8>, dst: NonNull<u8>) {
+        let value = (*src.cast::<P>().as_ptr()).clone();
+        ptr::write(dst.cast::<P>().as_ptr(), value);
+    }
+
+    pub unsafe fn clone_boxed<P: Clone>(src: NonNull<u8>, dst: NonNull<u8>) {
+        let value = Box::new((*read_ptr::<P>(src).as_ptr()).clone());
+        write_ptr(dst, NonNull::new_unchecked(Box::into_raw(value)));
+    }
+
+    pub unsafe fn clone_shared<P>(src: NonNull<u8>, dst: NonNull<u8>) {
+        let value = read_ptr::<P>(src);
+        Arc::increment_strong_count(value.as_ptr());
+        write_ptr(dst, value);
+    }
+
+    pub unsafe fn make_unique_shared<P: Clone>(slot: NonNull<u8>) {
+        let mut value = Arc::from_raw(read_ptr::<P>(slot).as_ptr());
+        Arc::make_mut(&mut value);
+        write_ptr(slot, NonNull::new_unchecked(Arc::into_raw(value) as *mut P));
+    }
+
+    pub unsafe fn debug<P: Debug>(value: NonNull<u8>, f: &mut Formatter) -> fmt::Result {
+        (*value.cast::<P>().as_ptr()).fmt(f)
+    }
+
+    /// Writes a value to an uninitialized slot.
+    pub unsafe fn write<P>(slot: NonNull<u8>, storage: Storage, value: P) {
+        match storage {
+            Storage::Direct => ptr::write(slot.cast::<P>().as_ptr(), value),
+            Storage::Boxed => {
+                let value = Box::into_raw(Box::new(value));
+                write_ptr(slot, NonNull::new_unchecked(value));
+            }
+            Storage::Shared(_) => {
+                let value = Arc::into_raw(Arc::new(value)) as *mut P;
+                write_ptr(slot, NonNull::new_unchecked(value));
+            }
+        }
+    }
+
+    /// Reads the pointer stored in a slot for an indirectly-stored value.
+    pub unsafe fn read_ptr<P>(slot: NonNull<u8>) -> NonNull<P> {
+        ptr::read(slot.cast::<NonNull<P>>().as_ptr())
+    }
+
+    /// Writes the pointer for an indirectly-stored value to a slot.
+    pub unsafe fn write_ptr<P>(slot: NonNull<u8>, value: NonNull<P>) {
+        ptr::write(slot.cast::<NonNull<P>>().as_ptr(), value)
+    }
 }
 
 impl Subject {
@@ -84,145 +399,818 @@ impl Subject {
                 next_chunk_id: 0,
                 open_chunks: Vec::new(),
             }),
+            dependencies: OnceLock::new(),
         }
     }
 
-    fn alloc_prop<P>(&self) -> PropertyInfo {
+    /// Gets the subject for the type `T`, creating it if needed. Each distinct type has its own
+    /// subject, so this can be used to implement [`Extend::subject`] for generic types, where
+    /// each instantiation should get a different subject.
+    pub fn of<T: ?Sized + 'static>() -> &'static Subject {
+        static SUBJECTS: TypeTable<&'static Subject> = TypeTable::new();
+        SUBJECTS.get_or_insert_with(TypeId::of::<T>(), || Box::leak(Box::new(Subject::new())))
+    }
+
+    /// Describes all of the properties that currently exist for this subject.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use dynprops::{Dynamic, Extend, Property};
+    /// use std::any::TypeId;
+    ///
+    /// let prop = Property::<Dynamic, u32>::new().with_name("count");
+    /// let desc = Dynamic::subject()
+    ///     .properties()
+    ///     .into_iter()
+    ///     .find(|desc| desc.name.as_deref() == Some("count"))
+    ///     .unwrap();
+    /// assert_eq!(desc.type_id, TypeId::of::<u32>());
+    /// assert_eq!(desc.size, 4);
+    /// ```
+    pub fn properties(&self) -> Vec<PropertyDescriptor> {
+        let info = self.info.lock().unwrap();
+        let mut res = Vec::new();
+        for chunk in info.open_chunks.iter() {
+            let chunk = lock_ignore_poison(chunk);
+            for slot in chunk.props.iter().filter(|slot| !slot.hidden) {
+                res.push(slot.descriptor(chunk.id))
+            }
+        }
+        res.sort_by_key(|desc| (desc.chunk, desc.offset));
+        res
+    }
+
+    fn alloc_prop(&'static self, ty: &PropertyType) -> PropertyInfo {
         let mut info = self.info.lock().unwrap();
-        return info.alloc_prop::<P>();
+        info.alloc_prop(self, ty)
+    }
+}
+
+/// Describes a [`Property`] of a [`Subject`], as returned by [`Subject::properties`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct PropertyDescriptor {
+    /// The name of the property, if it was given one using [`Property::with_name`].
+    pub name: Option<Arc<str>>,
+
+    /// The [`TypeId`] of the values of the property.
+    pub type_id: TypeId,
+
+    /// The name of the type of the values of the property.
+    pub type_name: &'static str,
+
+    /// The size of the values of the property.
+    pub size: usize,
+
+    /// The alignment of the values of the property.
+    pub align: usize,
+
+    /// Whether values of the property need to be dropped.
+    pub needs_drop: bool,
+
+    /// The id of the chunk the property is stored in.
+    pub chunk: usize,
+
+    /// The offset of the property's slot in its chunk. Large values are stored outside of the
+    /// chunk, with the slot holding a pointer to them.
+    pub offset: usize,
+}
+
+impl Default for Subject {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-const MIN_CHUNK_BODY_SIZE: usize = 128;
+/// The size of the space for property values in every chunk.
+const CHUNK_BODY_SIZE: usize = 128;
+
+/// The alignment of every chunk. Values with a greater alignment requirement are stored
+/// indirectly.
+const CHUNK_ALIGN: usize = 16;
+
+/// The maximum size of a value which is stored directly in a chunk. Larger values are stored
+/// indirectly, so that they don't take up space that could be used for many smaller values.
+const MAX_DIRECT_SIZE: usize = CHUNK_BODY_SIZE / 4;
+
+/// The number of bits in a word of the initialization bitset of a chunk.
+const INIT_WORD_BITS: usize = usize::BITS as usize;
 
 impl SubjectInfo {
-    fn alloc_prop<P>(&mut self) -> PropertyInfo {
-        // Check for a suitable open chunk to add the property to
-        // TODO: Remove unusable open chunks
+    fn alloc_prop(&mut self, subject: &'static Subject, ty: &PropertyType) -> PropertyInfo {
+        // Check for a suitable open chunk to add the property to. Chunks are never closed, since
+        // space in them may be freed when a property is dropped.
         for chunk in self.open_chunks.iter() {
-            let mut chunk_value = chunk.lock().unwrap();
-            if let Some(prop_info) = chunk_value.try_alloc_prop::<P>() {
-                return prop_info(chunk.clone());
+            let mut chunk_value = lock_ignore_poison(chunk);
+            if let Some(prop_info) = chunk_value.try_alloc_prop(ty) {
+                return prop_info(subject, chunk.clone());
             }
         }
 
-        // Define a new chunk
-        let layout = Layout::from_size_align(
-            max(MIN_CHUNK_BODY_SIZE, mem::size_of::<P>()),
-            max(mem::align_of::<usize>(), mem::align_of::<P>()),
-        )
-        .unwrap();
-        let mut chunk = ChunkInfo::new(self.next_chunk_id, layout);
+        // Define a new chunk. Every value that isn't zero-sized needs at least one byte, so provide
+        // enough initialization bits to fill the chunk with single-byte values.
+        let mut chunk = ChunkInfo::new(self.next_chunk_id, CHUNK_BODY_SIZE);
         self.next_chunk_id += 1;
 
         // Allocate property in chunk
-        let prop_info = chunk.try_alloc_prop::<P>().unwrap();
+        let prop_info = chunk.try_alloc_prop(ty).unwrap();
         let chunk = Arc::new(Mutex::new(chunk));
         self.open_chunks.push(chunk.clone());
-        return prop_info(chunk);
+        prop_info(subject, chunk)
     }
 }
 
 impl ChunkInfo {
-    fn new(id: usize, layout: Layout) -> Self {
+    /// Creates a new chunk with (at least) the given number of initialization bits.
+    fn new(id: usize, num_init_bits: usize) -> Self {
+        let init_words = num_init_bits.div_ceil(INIT_WORD_BITS);
+        let header_size = align_up(init_words * mem::size_of::<AtomicUsize>(), CHUNK_ALIGN);
+        let layout = Layout::from_size_align(header_size + CHUNK_BODY_SIZE, CHUNK_ALIGN).unwrap();
         ChunkInfo {
             id,
             layout,
-            in_use_init_bits: 0,
-            in_use_size: 0,
-            drop_props: Vec::new(),
-        }
-    }
-
-    fn try_alloc_prop<P>(&mut self) -> Option<impl Fn(Arc<Mutex<ChunkInfo>>) -> PropertyInfo> {
-        let size = mem::size_of::<P>();
-        let align = mem::align_of::<P>();
-        if align <= self.layout.align() && self.in_use_init_bits != usize::MAX {
-            let offset = (self.in_use_size + align - 1) & !(align - 1);
-            let new_size = offset + size;
-            if new_size <= self.layout.size() {
-                self.in_use_size = new_size;
-                let init_bit_offset = self.in_use_init_bits.trailing_ones() as usize;
-                self.in_use_init_bits |= 1 << init_bit_offset;
-                if mem::needs_drop::<P>() {
-                    self.drop_props.push(DropPropertyInfo {
-                        offset,
-                        init_bit_offset,
-                        drop: Self::drop_in_place::<P>,
-                    });
+            init_words,
+            in_use_init_bits: vec![0; init_words],
+            in_use_size: header_size,
+            free_ranges: Vec::new(),
+            props: Vec::new(),
+            instances: HashSet::new(),
+            pins: 0,
+            pending_free: Vec::new(),
+        }
+    }
+
+    fn try_alloc_prop(
+        &mut self,
+        ty: &PropertyType,
+    ) -> Option<impl Fn(&'static Subject, Arc<Mutex<ChunkInfo>>) -> PropertyInfo> {
+        let init_bit_offset = self.find_free_init_bit()?;
+        let offset = self.try_alloc_range(ty.size, ty.align)?;
+        self.in_use_init_bits[init_bit_offset / INIT_WORD_BITS] |=
+            1 << (init_bit_offset % INIT_WORD_BITS);
+        let slot_id = NEXT_SLOT_ID.fetch_add(1, Ordering::Relaxed);
+        self.props.push(SlotInfo {
+            id: slot_id,
+            offset,
+            init_bit_offset,
+            value: ty.value,
+            storage: ty.storage,
+            drop: ty.drop,
+            clone: ty.clone,
+            name: None,
+            debug: None,
+            #[cfg(feature = "serde")]
+            serde: None,
+            hidden: ty.hidden,
+        });
+        let chunk_id = self.id;
+        let size = ty.size;
+        let storage = ty.storage;
+        Some(move |subject, chunk| PropertyInfo {
+            subject,
+            slot_id,
+            chunk_id,
+            chunk,
+            offset,
+            size,
+            init_bit_offset,
+            storage,
+        })
+    }
+
+    /// Finds the first initialization bit in this chunk which is not in use by a property.
+    fn find_free_init_bit(&self) -> Option<usize> {
+        let index = self
+            .in_use_init_bits
+            .iter()
+            .position(|word| *word != usize::MAX)?;
+        Some(index * INIT_WORD_BITS + self.in_use_init_bits[index].trailing_ones() as usize)
+    }
+
+    /// Attempts to reserve an unused, suitably-aligned range of the given size in this chunk,
+    /// returning its offset.
+    fn try_alloc_range(&mut self, size: usize, align: usize) -> Option<usize> {
+        // Zero-sized values don't need any space. The start of the chunk is always aligned.
+        if size == 0 {
+            return Some(0);
+        }
+
+        // Try to reuse space freed by a dropped property
+        for i in 0..self.free_ranges.len() {
+            let range = self.free_ranges[i].clone();
+            let offset = align_up(range.start, align);
+            if offset + size <= range.end {
+                self.free_ranges.remove(i);
+                self.free_range(range.start..offset);
+                self.free_range((offset + size)..range.end);
+                return Some(offset);
+            }
+        }
+
+        // Allocate at the end of the chunk
+        let offset = align_up(self.in_use_size, align);
+        let new_size = offset + size;
+        if new_size <= self.layout.size() {
+            let padding = self.in_use_size..offset;
+            self.in_use_size = new_size;
+            self.free_range(padding);
+            return Some(offset);
+        }
+        None
+    }
+
+    /// Marks the given range of this chunk as unused.
+    fn free_range(&mut self, range: Range<usize>) {
+        if range.start >= range.end {
+            return;
+        }
+        let index = self
+            .free_ranges
+            .partition_point(|other| other.end < range.start);
+        let mut merged = range;
+        while index < self.free_ranges.len() && self.free_ranges[index].start <= merged.end {
+            let other = self.free_ranges.remove(index);
+            merged = merged.start.min(other.start)..merged.end.max(other.end);
+        }
+        if merged.end == self.in_use_size {
+            self.in_use_size = merged.start;
+        } else {
+            self.free_ranges.insert(index, merged);
+        }
+    }
+
+    /// Gets the [`SlotInfo`] for the property with the given initialization bit.
+    fn slot_mut(&mut self, init_bit_offset: usize) -> &mut SlotInfo {
+        self.props
+            .iter_mut()
+            .find(|slot| slot.init_bit_offset == init_bit_offset)
+            .unwrap()
+    }
+
+    /// Frees the space used by the given property, returning its [`SlotInfo`] along with its
+    /// values, which are moved out of the chunk. The values should be dropped after the chunk
+    /// info is unlocked, since dropping them may drop other objects which use this chunk. If the
+    /// chunk is pinned, the slot is freed once it is unpinned instead.
+    fn free_prop(&mut self, info: &PropertyInfo) -> (SlotInfo, DroppedValues) {
+        let index = self
+            .props
+            .iter()
+            .position(|slot| slot.init_bit_offset == info.init_bit_offset)
+            .unwrap();
+        let slot = self.props.swap_remove(index);
+        let pending = PendingFree {
+            offset: info.offset,
+            size: info.size,
+            init_bit_offset: info.init_bit_offset,
+            drop: slot.drop,
+        };
+        if self.pins > 0 {
+            self.pending_free.push(pending);
+            return (slot, DroppedValues::new(None, 0, 0));
+        }
+        let values = self.free_slot(pending);
+        (slot, values)
+    }
+
+    /// Frees the space used by a slot, moving its values out of the chunk.
+    fn free_slot(&mut self, slot: PendingFree) -> DroppedValues {
+        let mut values = DroppedValues::new(slot.drop, slot.size, self.instances.len());
+        for &ptr in self.instances.iter() {
+            unsafe {
+                let chunk = ChunkPtr(NonNull::new_unchecked(ptr as *mut u8));
+                if chunk.clear_init(slot.init_bit_offset) {
+                    values.push(chunk.slot_ptr(slot.offset));
                 }
-                let chunk_id = self.id;
-                return Some(move |chunk| PropertyInfo {
-                    chunk_id,
-                    chunk,
-                    offset,
-                    init_bit_offset,
-                });
             }
         }
-        return None;
+        self.in_use_init_bits[slot.init_bit_offset / INIT_WORD_BITS] &=
+            !(1 << (slot.init_bit_offset % INIT_WORD_BITS));
+        self.free_range(slot.offset..(slot.offset + slot.size));
+        values
+    }
+}
+
+/// Allows the values in the instances of a chunk to be read without holding the chunk info lock.
+/// Properties which are dropped in the meantime keep their values until this is dropped. This is
+/// needed when values are passed to user code (e.g. [`Clone`]), which may access other objects
+/// that use the same chunk.
+struct ChunkPin<'a> {
+    info: &'a Mutex<ChunkInfo>,
+}
+
+impl<'a> ChunkPin<'a> {
+    /// Pins a chunk, given its locked info.
+    fn new(info: &'a Mutex<ChunkInfo>, value: &mut ChunkInfo) -> Self {
+        value.pins += 1;
+        ChunkPin { info }
+    }
+}
+
+impl Drop for ChunkPin<'_> {
+    fn drop(&mut self) {
+        let mut info = lock_ignore_poison(self.info);
+        info.pins -= 1;
+        let mut values = Vec::new();
+        if info.pins == 0 {
+            for slot in mem::take(&mut info.pending_free) {
+                values.push(info.free_slot(slot));
+            }
+        }
+        drop(info);
+        drop(values);
+    }
+}
+
+/// The values of a dropped property, which were moved out of their chunks so that they can be
+/// dropped without holding the chunk info lock.
+struct DroppedValues {
+    drop: Option<unsafe fn(NonNull<u8>)>,
+    buffer: NonNull<u8>,
+    layout: Layout,
+    size: usize,
+
+    /// The distance between consecutive values in `buffer`.
+    stride: usize,
+    len: usize,
+}
+
+impl DroppedValues {
+    /// Creates a buffer for up to `capacity` slots of the given size. Nothing is stored if the
+    /// values don't need to be dropped.
+    fn new(drop: Option<unsafe fn(NonNull<u8>)>, size: usize, capacity: usize) -> Self {
+        let stride = align_up(size, CHUNK_ALIGN);
+        let capacity = if drop.is_some() { capacity } else { 0 };
+        let layout = Layout::from_size_align(stride * capacity, CHUNK_ALIGN).unwrap();
+        let buffer = if layout.size() == 0 {
+            NonNull::new(CHUNK_ALIGN as *mut u8).unwrap()
+        } else {
+            NonNull::new(unsafe { alloc(layout) }).unwrap_or_else(|| handle_alloc_error(layout))
+        };
+        DroppedValues {
+            drop,
+            buffer,
+            layout,
+            size,
+            stride,
+            len: 0,
+        }
     }
 
-    unsafe fn drop_in_place<P>(ptr: NonNull<u8>) {
-        ptr::drop_in_place(ptr.cast::<P>().as_ptr());
+    /// Moves the value in the given slot into this buffer, if it needs to be dropped.
+    unsafe fn push(&mut self, slot: NonNull<u8>) {
+        if self.drop.is_some() {
+            let dst = self.buffer.as_ptr().add(self.len * self.stride);
+            ptr::copy_nonoverlapping(slot.as_ptr(), dst, self.size);
+            self.len += 1;
+        }
+    }
+}
+
+impl Drop for DroppedValues {
+    fn drop(&mut self) {
+        if let Some(drop) = self.drop {
+            for index in 0..self.len {
+                unsafe {
+                    drop(NonNull::new_unchecked(
+                        self.buffer.as_ptr().add(index * self.stride),
+                    ))
+                };
+            }
+        }
+        if self.layout.size() > 0 {
+            unsafe { dealloc(self.buffer.as_ptr(), self.layout) };
+        }
+    }
+}
+
+/// The id of the next property to be allocated in any chunk.
+static NEXT_SLOT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Locks a mutex, ignoring poisoning. This is used for chunk bookkeeping, which stays consistent
+/// when a property's `clone` implementation panics while it is locked.
+fn lock_ignore_poison<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Rounds `offset` up to the nearest multiple of `align`, which must be a power of two.
+fn align_up(offset: usize, align: usize) -> usize {
+    (offset + align - 1) & !(align - 1)
+}
+
+/// A map keyed by [`TypeId`] which can be read without locking. Entries can't be removed, and
+/// are typically `'static` references to values created for each type.
+struct TypeTable<V: 'static> {
+    /// The current contents of the table. This is never modified; when an entry is added, it is
+    /// replaced by an updated copy. Old copies are leaked, since concurrent readers may still be
+    /// using them, but this only happens once per entry.
+    entries: AtomicPtr<HashMap<TypeId, V>>,
+
+    /// This lock must be held while adding an entry.
+    write: Mutex<()>,
+}
+
+impl<V: Copy + 'static> TypeTable<V> {
+    const fn new() -> Self {
+        TypeTable {
+            entries: AtomicPtr::new(ptr::null_mut()),
+            write: Mutex::new(()),
+        }
+    }
+
+    /// Gets the entry for the given type, if any.
+    fn get(&self, id: TypeId) -> Option<V> {
+        let entries = unsafe { self.entries.load(Ordering::Acquire).as_ref() };
+        entries.and_then(|entries| entries.get(&id).copied())
+    }
+
+    /// Gets the entry for the given type, using `init` to create it if needed. `init` is called
+    /// at most once per type.
+    fn get_or_insert_with(&self, id: TypeId, init: impl FnOnce() -> V) -> V {
+        if let Some(value) = self.get(id) {
+            return value;
+        }
+        let _guard = lock_ignore_poison(&self.write);
+        if let Some(value) = self.get(id) {
+            return value;
+        }
+        let value = init();
+        let mut entries = unsafe { self.entries.load(Ordering::Acquire).as_ref() }
+            .cloned()
+            .unwrap_or_default();
+        entries.insert(id, value);
+        self.entries
+            .store(Box::into_raw(Box::new(entries)), Ordering::Release);
+        value
     }
 }
 
 /// Identifies a property that is present on objects of type `T`.
+///
+/// When a property is dropped, its values are dropped on all objects and the space used to store
+/// them is made available to new properties.
+///
+/// Each property has an initializer which provides its value on an object where it has not been
+/// set. Values of a property will not be copied when an object is cloned, unless a different
+/// [`ClonePolicy`] is specified using [`Property::with_clone_policy`].
+///
+/// Since objects may be shared between threads, property values must be [`Send`]. A property can
+/// only be shared between threads (allowing concurrent access to its values) if its values are
+/// also [`Sync`].
 pub struct Property<T: Extend, P> {
     info: PropertyInfo,
-    _phantom: PhantomData<fn(T) -> P>,
+    init: Box<dyn Fn(&T) -> P + Send + Sync>,
+    observers: Observers<T, P>,
+
+    /// Copies a value of the property, so that it can be restored by [`Savepoint::rollback`]
+    /// after it is mutated or taken. This is only available for transactional properties.
+    snapshot: Option<fn(&P) -> P>,
+    _phantom: PhantomData<fn(&T)>,
+    _value: PhantomData<P>,
 }
 
-impl<T: Extend, P> Property<T, P> {
-    /// Creates a new property.
+impl<T: Extend, P: Send + Default + 'static> Property<T, P> {
+    /// Creates a new property whose values are initialized to [`Default::default()`].
     pub fn new() -> Self {
+        Self::with_init(|_| Default::default())
+    }
+}
+
+impl<T: Extend, P: Send + Sync + Clone + 'static> Property<T, P> {
+    /// Creates a new property whose values are initialized to a copy of `value`.
+    pub fn with_const_init(value: P) -> Self {
+        Self::with_init(move |_| value.clone())
+    }
+}
+
+impl<T: Extend, P: Send> Property<T, P> {
+    /// Creates a new property whose values are initialized by calling `init` on the object they
+    /// are for. The initializer may access other properties of the object, as long as they don't
+    /// depend on this property for their initialization.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use dynprops::{Dynamic, Property};
+    ///
+    /// let name = Property::with_const_init("Foo");
+    /// let len = Property::with_init(move |obj| name.get(obj).len());
+    /// let obj = Dynamic::new();
+    /// assert_eq!(*len.get(&obj), 3);
+    /// ```
+    pub fn with_init(init: impl Fn(&T) -> P + Send + Sync + 'static) -> Self
+    where
+        P: 'static,
+    {
         Self {
-            info: T::subject().alloc_prop::<P>(),
+            info: T::subject().alloc_prop(&PropertyType::new::<P>()),
+            init: Box::new(init),
+            observers: Observers::new(),
+            snapshot: None,
             _phantom: PhantomData,
+            _value: PhantomData,
         }
     }
 
+    /// Gets the value of this property on the given object. If the property has never been
+    /// accessed before, it's value will be initialized using the property's initializer.
+    pub fn get<'a>(&'a self, obj: &'a T) -> &'a P {
+        computed::track_read(obj, &self.info);
+        unsafe { obj.prop_data().source.get(&self.info, || (self.init)(obj)) }
+    }
+
+    /// Gets a mutable reference to the value of this property on the given object. If the property
+    /// has never been accessed before, it's value will be initialized using the property's
+    /// initializer.
+    pub fn get_mut<'a>(&'a mut self, obj: &'a T) -> PropertyMut<'a, P> {
+        let init = &self.init;
+        let snapshot = self.snapshot;
+        Self::get_mut_observed(&self.info, &mut self.observers, snapshot, obj, || init(obj))
+    }
+
     /// Gets the value of this property on the given object. If the property has never been
     /// accessed before, it's value will be initialized using `init`.
     pub fn get_with_init<'a>(&'a self, obj: &'a T, init: impl Fn() -> P) -> &'a P {
+        computed::track_read(obj, &self.info);
         unsafe { obj.prop_data().source.get(&self.info, init) }
     }
 
     /// Gets a mutable reference to the value of this property on the given object. If the property
     /// has never been accessed before, it's value will be initialized using `init`.
-    pub fn get_mut_with_init<'a>(&'a mut self, obj: &'a T, init: impl Fn() -> P) -> &'a mut P {
-        unsafe { obj.prop_data().source.get_mut(&self.info, init) }
+    pub fn get_mut_with_init<'a>(
+        &'a mut self,
+        obj: &'a T,
+        init: impl Fn() -> P,
+    ) -> PropertyMut<'a, P> {
+        let snapshot = self.snapshot;
+        Self::get_mut_observed(&self.info, &mut self.observers, snapshot, obj, init)
+    }
+
+    /// Gets a mutable reference to the value of a property on the given object, notifying
+    /// observers of the property when it is released.
+    fn get_mut_observed<'a>(
+        info: &'a PropertyInfo,
+        observers: &'a mut Observers<T, P>,
+        snapshot: Option<fn(&P) -> P>,
+        obj: &'a T,
+        init: impl Fn() -> P,
+    ) -> PropertyMut<'a, P> {
+        computed::invalidate(obj, info);
+        let data = &obj.prop_data().source;
+        if data.is_recording() {
+            match (unsafe { data.try_get(info) }, snapshot) {
+                (Some(value), Some(snapshot)) => unsafe {
+                    data.record(info, Some(snapshot(value)))
+                },
+                (Some(_), None) => data.record_unrestorable::<P>(info),
+                (None, _) => unsafe { data.record::<P>(info, None) },
+            }
+        }
+        if !observers.is_observed(obj) {
+            return unsafe { data.get_mut(info, init) };
+        }
+        let observers = &*observers;
+        let old = unsafe { data.try_get(info) }.map(|value| observers.snapshot(value));
+        let mut res = unsafe { data.get_mut(info, init) };
+        res.on_drop = Some(Box::new(move || {
+            let new = unsafe { data.try_get(info) }.unwrap();
+            observers.notify(obj, old.as_ref(), new);
+        }));
+        res
+    }
+
+    /// Gets the value of this property on the given object, or [`None`] if it has not been
+    /// initialized.
+    pub fn try_get<'a>(&'a self, obj: &'a T) -> Option<&'a P> {
+        computed::track_read(obj, &self.info);
+        unsafe { obj.prop_data().source.try_get(&self.info) }
+    }
+
+    /// Determines whether this property has been initialized on the given object.
+    pub fn is_set(&self, obj: &T) -> bool {
+        computed::track_read(obj, &self.info);
+        obj.prop_data().source.is_set(&self.info)
     }
 
     /// Sets the value of this property on the given object.
     pub fn set(&mut self, obj: &T, value: P) {
-        unsafe { obj.prop_data().source.set(&self.info, value) }
+        computed::invalidate(obj, &self.info);
+        let data = &obj.prop_data().source;
+        let is_observed = self.observers.is_observed(obj);
+        if !is_observed && !data.is_recording() {
+            return unsafe { data.set(&self.info, value) };
+        }
+        let old = unsafe { data.replace(&self.info, value) };
+        if is_observed {
+            let new = unsafe { data.try_get(&self.info) }.unwrap();
+            self.observers.notify(obj, old.as_ref(), new);
+        }
+        unsafe { data.record(&self.info, old) };
+    }
+
+    /// Removes the value of this property from the given object, returning it if it was
+    /// initialized. The property will be reinitialized the next time it is accessed.
+    pub fn take(&mut self, obj: &T) -> Option<P> {
+        self.take_recorded(obj)
+    }
+
+    /// Drops the value of this property on the given object, if it was initialized. The property
+    /// will be reinitialized the next time it is accessed.
+    pub fn unset(&mut self, obj: &T) {
+        self.unset_recorded(obj);
+    }
+
+    /// Drops the value of this property on the given object, returning whether it was
+    /// initialized. Unlike [`Property::unset`], this only requires exclusive access to the
+    /// object, rather than to the property, so it can be used with a property in a `static`.
+    pub fn discard(&self, obj: &mut T) -> bool {
+        self.unset_recorded(obj)
+    }
+
+    /// Removes the value of this property from the given object. If there is an active
+    /// [`Savepoint`] for the object, a copy of the value is recorded, or the change is recorded
+    /// as unrestorable if the property isn't transactional.
+    fn take_recorded(&self, obj: &T) -> Option<P> {
+        computed::invalidate(obj, &self.info);
+        let data = &obj.prop_data().source;
+        let value = unsafe { data.take(&self.info) }?;
+        if data.is_recording() {
+            match self.snapshot {
+                Some(snapshot) => unsafe { data.record(&self.info, Some(snapshot(&value))) },
+                None => data.record_unrestorable::<P>(&self.info),
+            }
+        }
+        Some(value)
+    }
+
+    /// Drops the value of this property on the given object, moving it into the journal if there
+    /// is an active [`Savepoint`] for the object. Returns whether the value was initialized.
+    fn unset_recorded(&self, obj: &T) -> bool {
+        computed::invalidate(obj, &self.info);
+        let data = &obj.prop_data().source;
+        let old: Option<P> = unsafe { data.take(&self.info) };
+        let was_set = old.is_some();
+        unsafe { data.record(&self.info, old) };
+        was_set
     }
 }
 
-impl<T: Extend, P: Default> Property<T, P> {
-    /// Gets the value of this property on the given object. If the property has never been
-    /// accessed before, it's value will be initialized to [`Default::default()`].
-    pub fn get<'a>(&'a self, obj: &'a T) -> &'a P {
-        unsafe {
-            obj.prop_data()
-                .source
-                .get(&self.info, || Default::default())
+impl<T: Extend, P: Send + Sync + Clone + 'static> Property<T, P> {
+    /// Sets the [`ClonePolicy`] for this property, which determines what happens to its values
+    /// when an object is cloned. Any existing values of the property are dropped.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use dynprops::{ClonePolicy, Dynamic, Property};
+    ///
+    /// let mut name = Property::new().with_clone_policy(ClonePolicy::Clone);
+    /// let mut selected = Property::new().with_clone_policy(ClonePolicy::Reset);
+    /// let obj = Dynamic::new();
+    /// name.set(&obj, "Foo");
+    /// selected.set(&obj, true);
+    /// let clone = obj.clone();
+    /// assert_eq!(*name.get(&clone), "Foo");
+    /// assert_eq!(*selected.get(&clone), false);
+    /// ```
+    pub fn with_clone_policy(mut self, policy: ClonePolicy) -> Self {
+        let info = T::subject().alloc_prop(&PropertyType::with_clone_policy::<P>(policy));
+        let old_info = mem::replace(&mut self.info, info);
+        let (old_slot, old_values) = lock_ignore_poison(&old_info.chunk).free_prop(&old_info);
+        drop(old_values);
+        let mut chunk = lock_ignore_poison(&self.info.chunk);
+        let slot = chunk.slot_mut(self.info.init_bit_offset);
+        slot.name = old_slot.name;
+        slot.debug = old_slot.debug;
+        #[cfg(feature = "serde")]
+        {
+            slot.serde = old_slot.serde;
         }
+        drop(chunk);
+        self
     }
 
-    /// Gets a mutable reference to the value of this property on the given object. If the property
-    /// has never been accessed before, it's value will be initialized to [`Default::default()`].
-    pub fn get_mut<'a>(&'a mut self, obj: &'a T) -> &'a mut P {
-        unsafe {
-            obj.prop_data()
-                .source
-                .get_mut(&self.info, || Default::default())
+    /// Makes this property transactional, so that values which are mutated through
+    /// [`Property::get_mut`] or removed through [`Property::take`] while there is an active
+    /// [`Savepoint`] for an object can be restored. The previous value is copied so that it can be
+    /// restored when the object is rolled back. Without this, rolling back such a change keeps the
+    /// current value and reports the property in a [`RollbackError`]. Values [set](Property::set) or [unset](Property::unset) during a
+    /// savepoint are always restored, since those don't need a copy.
+    pub fn transactional(mut self) -> Self {
+        self.snapshot = Some(P::clone);
+        self
+    }
+}
+
+impl<T: Extend, P: Send + Sync + fmt::Debug> Property<T, P> {
+    /// Gives this property a name, allowing its values to be shown when the [`PropertyData`] for
+    /// an object is formatted using [`Debug`](fmt::Debug).
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use dynprops::{Dynamic, Property};
+    ///
+    /// let mut pressure = Property::new().with_name("pressure");
+    /// let obj = Dynamic::new();
+    /// pressure.set(&obj, 32.5);
+    /// assert_eq!(format!("{:?}", obj), "Dynamic { prop_data: PropertyData { pressure: 32.5 } }");
+    /// ```
+    pub fn with_name(self, name: impl Into<Arc<str>>) -> Self {
+        let mut chunk = lock_ignore_poison(&self.info.chunk);
+        let slot = chunk.slot_mut(self.info.init_bit_offset);
+        slot.name = Some(name.into());
+        slot.debug = Some(slot::debug::<P>);
+        drop(chunk);
+        self
+    }
+}
+
+impl<T: Extend, P: Send + Default + 'static> Default for Property<T, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Extend, P> Drop for Property<T, P> {
+    fn drop(&mut self) {
+        let (_, values) = lock_ignore_poison(&self.info.chunk).free_prop(&self.info);
+        drop(values);
+    }
+}
+
+/// Creates a new property whose values are initialized to a copy of `value`.
+pub fn new_prop_const_init<T: Extend, P: Send + Sync + Clone + 'static>(
+    value: P,
+) -> Property<T, P> {
+    Property::with_const_init(value)
+}
+
+/// Creates a new property whose values are initialized to [`Default::default()`].
+pub fn new_prop_default_init<T: Extend, P: Send + Default + 'static>() -> Property<T, P> {
+    Property::new()
+}
+
+/// Creates a new property whose values are initialized by calling `init` on the object they are
+/// for.
+pub fn new_prop_fn_init<T: Extend, P: Send + 'static>(
+    init: impl Fn(&T) -> P + Send + Sync + 'static,
+) -> Property<T, P> {
+    Property::with_init(init)
+}
+
+/// Determines what happens to the value of a [`Property`] when the [`PropertyData`] containing it
+/// is cloned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClonePolicy {
+    /// The value is [cloned](Clone::clone) into the new object.
+    Clone,
+
+    /// The property is left uninitialized on the new object.
+    Reset,
+
+    /// The value is shared between the original object and the new one until either of them
+    /// mutates it, at which point it is cloned. This is useful for values which are expensive to
+    /// clone and rarely mutated.
+    Share,
+}
+
+/// A mutable reference to the value of a [`Property`] on an object.
+///
+/// While this exists, operations that read all of the properties of the object, such as cloning
+/// its [`PropertyData`], will block. Such operations panic if they are performed on the thread
+/// which holds this, since they would never complete. Observers of the property are notified
+/// when it is dropped.
+pub struct PropertyMut<'a, P> {
+    value: &'a mut P,
+    guard: Option<MutationGuard<'a>>,
+
+    /// Called after the mutation is complete.
+    on_drop: Option<Box<dyn FnOnce() + 'a>>,
+}
+
+impl<P> Drop for PropertyMut<'_, P> {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+        if let Some(on_drop) = self.on_drop.take() {
+            on_drop();
         }
     }
 }
 
+impl<P> Deref for PropertyMut<'_, P> {
+    type Target = P;
+    fn deref(&self) -> &P {
+        self.value
+    }
+}
+
+impl<P> DerefMut for PropertyMut<'_, P> {
+    fn deref_mut(&mut self) -> &mut P {
+        self.value
+    }
+}
+
 /// A value consisting entirely of dynamic [`Property`]s.
 ///
 /// ## Example
@@ -237,7 +1225,7 @@ impl<T: Extend, P: Default> Property<T, P> {
 /// *prop.get_mut(&obj) = "Bar";
 /// assert_eq!(*prop.get(&obj), "Bar");
 /// ```
-#[derive(Extend)]
+#[derive(Extend, Clone, Debug)]
 pub struct Dynamic {
     #[prop_data]
     prop_data: PropertyData<Dynamic>,
@@ -252,6 +1240,12 @@ impl Dynamic {
     }
 }
 
+impl Default for Dynamic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Augments a value with the ability to store dynamic [`Property`]s.
 ///
 /// ## Example
@@ -267,7 +1261,7 @@ impl Dynamic {
 /// *prop.get_mut(&obj) = "Bar";
 /// assert_eq!(*prop.get(&obj), "Bar");
 /// ```
-#[derive(Extend)]
+#[derive(Extend, Clone, Debug)]
 pub struct Extended<T> {
     pub value: T,
     #[prop_data]
@@ -285,9 +1279,13 @@ impl<T> Extended<T> {
 }
 
 /// Encapsulates the values for all the [`Property`]s on an object of the given type.
+///
+/// This is always [`Send`] and [`Sync`], since [`Property`] only allows thread-safe values to be
+/// stored and shared.
+#[repr(transparent)]
 pub struct PropertyData<T: ?Sized> {
     source: RawPropertyData,
-    _marker: PhantomData<dyn Fn() -> T>,
+    _marker: PhantomData<fn() -> T>,
 }
 
 impl<T> PropertyData<T> {
@@ -298,221 +1296,687 @@ impl<T> PropertyData<T> {
             _marker: PhantomData,
         }
     }
+
+    /// Reinterprets this as the [`PropertyData`] for another type. This is used to implement
+    /// [`Extend`] for a type which wraps another [`Extend`] type.
+    ///
+    /// # Safety
+    ///
+    /// `U` and `T` must use the same [`Subject`].
+    pub unsafe fn cast<U>(&self) -> &PropertyData<U> {
+        &*(self as *const PropertyData<T> as *const PropertyData<U>)
+    }
+}
+
+impl<T> Default for PropertyData<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lists the values of every named [`Property`] which is initialized on the object. This will
+/// block while any property value on the object is being mutated.
+impl<T> fmt::Debug for PropertyData<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+/// Creates a copy of an object's property values, with each property's [`ClonePolicy`]
+/// determining how its value is copied. This will block while any property value on the object is
+/// being mutated.
+impl<T> Clone for PropertyData<T> {
+    fn clone(&self) -> Self {
+        Self {
+            source: self.source.clone(),
+            _marker: PhantomData,
+        }
+    }
 }
 
 /// Encapsulates the values for all the [`Property`]s on an object.
+///
+/// Chunks are looked up through an append-only [`ChunkTable`], and initialized values are never
+/// moved, so reading an initialized property does not require taking any locks.
 struct RawPropertyData {
+    /// The table used to look up chunks by id. This may be read without holding `chunks`.
+    table: AtomicPtr<ChunkTable>,
+
+    /// The chunks owned by this [`RawPropertyData`]. This lock must be held while adding a chunk.
     chunks: Mutex<Vec<Chunk>>,
+
+    /// Tracks ongoing operations on this [`RawPropertyData`] which need to be coordinated.
+    state: Mutex<AccessState>,
+
+    /// Signaled whenever an operation in `state` completes.
+    state_changed: Condvar,
+
+    /// Records changes to property values while there is an active [`Savepoint`].
+    journal: Mutex<Journal>,
+
+    /// Entries which were encountered during deserialization that don't correspond to any
+    /// property. These are kept so that they can be written back when serializing.
+    #[cfg(feature = "serde")]
+    unknown: Vec<(Arc<str>, serde_impl::UnknownValue)>,
+}
+
+struct AccessState {
+    /// The property values which are currently being initialized.
+    initializing: Vec<Initialization>,
+
+    /// The threads which are currently mutating property values, once for each mutation.
+    mutations: Vec<ThreadId>,
+
+    /// The threads performing operations which read all property values (e.g. cloning), once for
+    /// each operation. These can't happen at the same time as a mutation.
+    readers: Vec<ThreadId>,
+}
+
+impl AccessState {
+    /// Removes one occurrence of the current thread from the given list, returning whether the
+    /// list is now empty.
+    fn remove_current(list: &mut Vec<ThreadId>) -> bool {
+        let thread = thread::current().id();
+        let index = list.iter().position(|t| *t == thread).unwrap();
+        list.swap_remove(index);
+        list.is_empty()
+    }
+}
+
+/// Identifies a property value in [`RawPropertyData`] which is being initialized by a thread.
+struct Initialization {
+    chunk_id: usize,
+    init_bit_offset: usize,
+    thread: ThreadId,
+}
+
+/// Removes an [`Initialization`] when dropped, waking any threads waiting for it to complete.
+/// This is dropped after the value is initialized, or if the initializer panics.
+struct InitializationGuard<'a> {
+    data: &'a RawPropertyData,
+    chunk_id: usize,
+    init_bit_offset: usize,
+}
+
+impl Drop for InitializationGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.data.state.lock().unwrap();
+        state.initializing.retain(|init| {
+            init.chunk_id != self.chunk_id || init.init_bit_offset != self.init_bit_offset
+        });
+        self.data.state_changed.notify_all();
+    }
+}
+
+/// Represents an ongoing mutation of a property value in [`RawPropertyData`].
+struct MutationGuard<'a> {
+    data: &'a RawPropertyData,
+}
+
+impl Drop for MutationGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.data.state.lock().unwrap();
+        if AccessState::remove_current(&mut state.mutations) {
+            self.data.state_changed.notify_all();
+        }
+    }
+}
+
+/// Represents an ongoing operation which reads all property values in [`RawPropertyData`].
+struct ReadAllGuard<'a> {
+    data: &'a RawPropertyData,
+}
+
+impl Drop for ReadAllGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.data.state.lock().unwrap();
+        if AccessState::remove_current(&mut state.readers) {
+            self.data.state_changed.notify_all();
+        }
+    }
+}
+
+// Values may only be stored through a `Property` whose value type is `Send`, and they may only be
+// accessed concurrently through a shared `Property`, which requires the value type to be `Sync`.
+// All other shared state is either atomic or behind a lock.
+unsafe impl Send for RawPropertyData {}
+unsafe impl Sync for RawPropertyData {}
+
+/// An append-only table of chunk pointers, indexed by chunk id. When the table needs to grow, it
+/// is replaced by a larger copy. The old table is kept alive (through `_prev`) since concurrent
+/// readers may still be using it.
+struct ChunkTable {
+    entries: Box<[AtomicPtr<u8>]>,
+    _prev: Option<Box<ChunkTable>>,
 }
 
+const MIN_CHUNK_TABLE_LEN: usize = 4;
+
 impl RawPropertyData {
     /// Creates a [`RawPropertyData`] with all properties uninitialized.
     pub fn new() -> Self {
         RawPropertyData {
+            table: AtomicPtr::new(ptr::null_mut()),
             chunks: Mutex::new(Vec::new()),
+            state: Mutex::new(AccessState {
+                initializing: Vec::new(),
+                mutations: Vec::new(),
+                readers: Vec::new(),
+            }),
+            state_changed: Condvar::new(),
+            journal: Mutex::new(Journal::new()),
+            #[cfg(feature = "serde")]
+            unknown: Vec::new(),
         }
     }
 
     /// Gets a dynamic property in this [`RawPropertyData`], initializing it if needed.
     unsafe fn get<P>(&self, info: &PropertyInfo, initer: impl Fn() -> P) -> &P {
-        // Search for chunk
-        let mut chunks = self.chunks.lock().unwrap();
-        match Self::find_chunk_mut(&mut chunks, info.chunk_id) {
-            Ok(chunk) => {
-                if let Some(res) = chunk.try_get_mut::<P>(info.offset, info.init_bit_offset) {
-                    // Extending lifetime here because we need to drop the lock while returning
-                    // a reference to something behind it. This is okay because the contents of the
-                    // reference are initialized and can't change anymore (without a mutable
-                    // reference to the the property).
-                    return mem::transmute(res);
-                }
-            }
-            Err(_) => {}
+        let chunk = self.get_init_chunk(info, initer);
+        &*chunk.value_ptr::<P>(info).as_ptr()
+    }
+
+    /// Gets a mutable reference to a dynamic property in this [`RawPropertyData`], initializing
+    /// it if needed.
+    unsafe fn get_mut<P>(&self, info: &PropertyInfo, initer: impl Fn() -> P) -> PropertyMut<'_, P> {
+        let chunk = self.get_init_chunk(info, initer);
+        let guard = self.begin_mutation();
+        PropertyMut {
+            value: &mut *chunk.value_ptr_mut::<P>(info).as_ptr(),
+            guard: Some(guard),
+            on_drop: None,
         }
+    }
 
-        // Initialize value (make sure not to hold lock due to the potential for recursive access)
-        // TODO: Prevent simultaneous initializations of same value
-        drop(chunks);
-        let init_value = initer();
-
-        // Search for chunk again
-        let mut chunks = self.chunks.lock().unwrap();
-        match Self::find_chunk_mut(&mut chunks, info.chunk_id) {
-            Ok(chunk) => {
-                let res = chunk.get_mut_with_init(info.offset, info.init_bit_offset, init_value);
-                return mem::transmute(res);
-            }
-            Err(after) => {
-                // Initialize chunk
-                let chunk = Chunk::new(&info.chunk);
-                chunks.insert(after, chunk);
-                let chunk = &mut chunks[after];
-                let res = chunk.get_mut_with_init(info.offset, info.init_bit_offset, init_value);
-                return mem::transmute(res);
-            }
+    /// Gets a dynamic property in this [`RawPropertyData`], or [`None`] if it hasn't been
+    /// initialized.
+    unsafe fn try_get<P>(&self, info: &PropertyInfo) -> Option<&P> {
+        let chunk = self.find_chunk(info.chunk_id)?;
+        let ptr = chunk.try_get_ptr::<P>(info)?;
+        Some(&*ptr.as_ptr())
+    }
+
+    /// Determines whether a dynamic property in this [`RawPropertyData`] has been initialized.
+    fn is_set(&self, info: &PropertyInfo) -> bool {
+        match self.find_chunk(info.chunk_id) {
+            Some(chunk) => chunk.is_init(info.init_bit_offset),
+            None => false,
         }
     }
 
-    /// Gets a mutable reference to a dynamic property in this [`RawPropertyData`], initializing
-    /// it if needed.
-    unsafe fn get_mut<P>(&self, info: &PropertyInfo, initer: impl Fn() -> P) -> &mut P {
-        // Search for chunk
-        let mut chunks = self.chunks.lock().unwrap();
-        match Self::find_chunk_mut(&mut chunks, info.chunk_id) {
-            Ok(chunk) => {
-                if let Some(res) = chunk.try_get_mut::<P>(info.offset, info.init_bit_offset) {
-                    return mem::transmute(res);
-                }
+    /// Gets the chunk for a dynamic property in this [`RawPropertyData`], initializing the
+    /// property if needed. The initializer will be called at most once per value, even when the
+    /// value is accessed concurrently; other threads wait for it to complete.
+    unsafe fn get_init_chunk<P>(&self, info: &PropertyInfo, initer: impl Fn() -> P) -> ChunkPtr {
+        // Fast path for initialized values. Once a value is initialized, it can't change or move
+        // without a mutable reference to the property.
+        if let Some(chunk) = self.find_chunk(info.chunk_id) {
+            if chunk.is_init(info.init_bit_offset) {
+                return chunk;
             }
-            Err(_) => {}
         }
 
-        // Initialize value (make sure not to hold lock due to the potential for recursive access)
-        // TODO: Prevent simultaneous initializations of same value
-        drop(chunks);
-        let init_value = initer();
-
-        // Search for chunk again
-        let mut chunks = self.chunks.lock().unwrap();
-        match Self::find_chunk_mut(&mut chunks, info.chunk_id) {
-            Ok(chunk) => {
-                let res = chunk.get_mut_with_init(info.offset, info.init_bit_offset, init_value);
-                return mem::transmute(res);
+        // Claim the initialization of the value, or wait for another thread to finish it
+        let chunk = {
+            let mut chunks = lock_ignore_poison(&self.chunks);
+            self.get_or_insert_chunk(&mut chunks, info)
+        };
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if chunk.is_init(info.init_bit_offset) {
+                return chunk;
             }
-            Err(after) => {
-                // Initialize chunk
-                let chunk = Chunk::new(&info.chunk);
-                chunks.insert(after, chunk);
-                let chunk = &mut chunks[after];
-                let res = chunk.get_mut_with_init(info.offset, info.init_bit_offset, init_value);
-                return mem::transmute(res);
+            match state.initializing.iter().find(|init| {
+                init.chunk_id == info.chunk_id && init.init_bit_offset == info.init_bit_offset
+            }) {
+                Some(init) => {
+                    if init.thread == thread::current().id() {
+                        drop(state);
+                        panic!("Property value depends on itself for initialization");
+                    }
+                    state = self.state_changed.wait(state).unwrap();
+                }
+                None => break,
             }
         }
+        state.initializing.push(Initialization {
+            chunk_id: info.chunk_id,
+            init_bit_offset: info.init_bit_offset,
+            thread: thread::current().id(),
+        });
+        drop(state);
+
+        // Initialize value (make sure not to hold lock due to the potential for recursive access)
+        let _guard = InitializationGuard {
+            data: self,
+            chunk_id: info.chunk_id,
+            init_bit_offset: info.init_bit_offset,
+        };
+        chunk.init(info, initer());
+        chunk
     }
 
     /// Sets the value of a dynamic property in this [`RawPropertyData`].
     unsafe fn set<P>(&self, info: &PropertyInfo, value: P) {
-        // Search for chunk
-        let mut chunks = self.chunks.lock().unwrap();
-        match Self::find_chunk_mut(&mut chunks, info.chunk_id) {
-            Ok(chunk) => {
-                chunk.set(info.offset, info.init_bit_offset, value);
+        let chunk = match self.find_chunk(info.chunk_id) {
+            Some(chunk) => chunk,
+            None => {
+                let mut chunks = lock_ignore_poison(&self.chunks);
+                self.get_or_insert_chunk(&mut chunks, info)
+            }
+        };
+        let _guard = self.begin_mutation();
+        chunk.set(info, value);
+    }
+
+    /// Sets the value of a dynamic property in this [`RawPropertyData`], returning the previous
+    /// value if it was initialized.
+    unsafe fn replace<P>(&self, info: &PropertyInfo, value: P) -> Option<P> {
+        let chunk = match self.find_chunk(info.chunk_id) {
+            Some(chunk) => chunk,
+            None => {
+                let mut chunks = lock_ignore_poison(&self.chunks);
+                self.get_or_insert_chunk(&mut chunks, info)
+            }
+        };
+        let _guard = self.begin_mutation();
+        chunk.replace(info, value)
+    }
+
+    /// Removes the value of a dynamic property in this [`RawPropertyData`], leaving it
+    /// uninitialized.
+    unsafe fn take<P>(&self, info: &PropertyInfo) -> Option<P> {
+        let chunk = self.find_chunk(info.chunk_id)?;
+        let _guard = self.begin_mutation();
+        chunk.take(info)
+    }
+
+    /// Gets a copy of the list of chunks in this [`RawPropertyData`]. This is useful for
+    /// operations which call user code (e.g. formatting) on values, since the `chunks` lock
+    /// should not be held when a value may access (and initialize) other properties.
+    fn chunk_list(&self) -> Vec<(Arc<Mutex<ChunkInfo>>, ChunkPtr)> {
+        lock_ignore_poison(&self.chunks)
+            .iter()
+            .map(|chunk| (chunk.info.clone(), chunk.ptr))
+            .collect()
+    }
+
+    /// Begins mutating a property value, waiting for operations that read all property values to
+    /// complete.
+    fn begin_mutation(&self) -> MutationGuard<'_> {
+        let thread = thread::current().id();
+        let mut state = self.state.lock().unwrap();
+        while !state.readers.is_empty() {
+            if state.readers.contains(&thread) {
+                drop(state);
+                panic!("Property value mutated while all properties of the object are being read");
             }
-            Err(after) => {
-                // Initialize chunk
-                let mut chunk = Chunk::new(&info.chunk);
-                chunk.set(info.offset, info.init_bit_offset, value);
-                chunks.insert(after, chunk);
+            state = self.state_changed.wait(state).unwrap();
+        }
+        state.mutations.push(thread);
+        MutationGuard { data: self }
+    }
+
+    /// Begins an operation that reads all property values, waiting for mutations to complete.
+    fn begin_read_all(&self) -> ReadAllGuard<'_> {
+        let thread = thread::current().id();
+        let mut state = self.state.lock().unwrap();
+        while !state.mutations.is_empty() {
+            if state.mutations.contains(&thread) {
+                drop(state);
+                panic!("All properties of an object read while one of them is being mutated");
             }
+            state = self.state_changed.wait(state).unwrap();
         }
+        state.readers.push(thread);
+        ReadAllGuard { data: self }
     }
 
-    /// Searches for the chunk with the given id within `chunks`. Returns a reference to the chunk
-    /// if found, or the index where the chunk would be if it existed.
-    fn find_chunk_mut(chunks: &mut Vec<Chunk>, chunk_id: usize) -> Result<&mut Chunk, usize> {
-        // Binary search for pre-existing chunk
-        let mut lo = 0;
-        if chunks.len() > 0 {
-            let mut hi = chunks.len();
-            loop {
-                if !(lo < hi) {
-                    break;
-                }
-                let mid = (lo + hi) / 2;
-                let mid_chunk = &mut chunks[mid];
-                if chunk_id < mid_chunk.id {
-                    hi = mid;
-                } else if chunk_id > mid_chunk.id {
-                    lo = mid + 1;
-                } else {
-                    // Need unsafe here because of limitations of borrow checker
-                    // https://github.com/rust-lang/rust/issues/43234
-                    unsafe {
-                        return Ok(mem::transmute(mid_chunk));
+    /// Looks up the chunk with the given id without locking.
+    fn find_chunk(&self, chunk_id: usize) -> Option<ChunkPtr> {
+        let table = self.table.load(Ordering::Acquire);
+        if table.is_null() {
+            return None;
+        }
+        let table = unsafe { &*table };
+        let entry = table.entries.get(chunk_id)?;
+        NonNull::new(entry.load(Ordering::Acquire)).map(ChunkPtr)
+    }
+
+    /// Gets the chunk for the given property, creating it if it doesn't exist. `chunks` must be
+    /// the locked contents of `self.chunks`.
+    fn get_or_insert_chunk(&self, chunks: &mut Vec<Chunk>, info: &PropertyInfo) -> ChunkPtr {
+        if let Some(chunk) = self.find_chunk(info.chunk_id) {
+            return chunk;
+        }
+        self.insert_chunk(chunks, info.chunk_id, Chunk::new(&info.chunk))
+    }
+
+    /// Adds a chunk with the given id to this [`RawPropertyData`]. `chunks` must be the locked
+    /// contents of `self.chunks`.
+    fn insert_chunk(&self, chunks: &mut Vec<Chunk>, chunk_id: usize, chunk: Chunk) -> ChunkPtr {
+        let ptr = chunk.ptr;
+        chunks.push(chunk);
+
+        // Grow table if needed
+        let mut table = self.table.load(Ordering::Acquire);
+        let len = if table.is_null() {
+            0
+        } else {
+            unsafe { (&*table).entries.len() }
+        };
+        if chunk_id >= len {
+            let new_len = max(max(MIN_CHUNK_TABLE_LEN, len * 2), chunk_id + 1);
+            let prev = if table.is_null() {
+                None
+            } else {
+                Some(unsafe { Box::from_raw(table) })
+            };
+            let entries = (0..new_len)
+                .map(|index| {
+                    let entry = prev.as_ref().and_then(|prev| prev.entries.get(index));
+                    AtomicPtr::new(
+                        entry.map_or(ptr::null_mut(), |entry| entry.load(Ordering::Relaxed)),
+                    )
+                })
+                .collect();
+            table = Box::into_raw(Box::new(ChunkTable {
+                entries,
+                _prev: prev,
+            }));
+            self.table.store(table, Ordering::Release);
+        }
+
+        // Publish chunk
+        unsafe {
+            (*table).entries[chunk_id].store(ptr.0.as_ptr(), Ordering::Release);
+        }
+        ptr
+    }
+}
+
+impl fmt::Debug for RawPropertyData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /// Formats a property value using its [`DebugFn`].
+        struct Value(NonNull<u8>, DebugFn);
+
+        impl fmt::Debug for Value {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                unsafe { (self.1)(self.0, f) }
+            }
+        }
+
+        let _guard = self.begin_read_all();
+        let chunks = self.chunk_list();
+        let mut res = f.debug_struct("PropertyData");
+        let mut has_unnamed = false;
+        for (info, chunk) in chunks.iter() {
+            // Values are formatted without holding the chunk info lock, since they may contain
+            // objects which use the same chunk
+            let mut info_value = lock_ignore_poison(info);
+            let mut fields = Vec::new();
+            for slot in info_value.props.iter() {
+                if chunk.is_init(slot.init_bit_offset) {
+                    match (&slot.name, slot.debug) {
+                        (Some(name), Some(debug)) => {
+                            let value = unsafe { chunk.slot_value_ptr(slot.offset, slot.storage) };
+                            fields.push((name.clone(), Value(value, debug)));
+                        }
+                        _ => has_unnamed = true,
                     }
                 }
             }
+            let _pin = ChunkPin::new(info, &mut info_value);
+            drop(info_value);
+            for (name, value) in fields.iter() {
+                res.field(name, value);
+            }
+        }
+        if has_unnamed {
+            res.finish_non_exhaustive()
+        } else {
+            res.finish()
+        }
+    }
+}
+
+impl Clone for RawPropertyData {
+    fn clone(&self) -> Self {
+        #[allow(unused_mut)]
+        let mut res = RawPropertyData::new();
+        #[cfg(feature = "serde")]
+        {
+            res.unknown = self.unknown.clone();
+        }
+        let _guard = self.begin_read_all();
+        for (info, chunk) in self.chunk_list() {
+            // Values are cloned without holding any locks, since they may contain objects which
+            // use the same chunk. The pin ensures that a property can't be dropped in the meantime.
+            let res_chunk = Chunk::new(&info);
+            let mut info_value = lock_ignore_poison(&info);
+            let chunk_id = info_value.id;
+            let slots = info_value
+                .props
+                .iter()
+                .filter(|slot| chunk.is_init(slot.init_bit_offset))
+                .filter_map(|slot| Some((slot.clone?, slot.offset, slot.init_bit_offset)))
+                .collect::<Vec<_>>();
+            let pin = ChunkPin::new(&info, &mut info_value);
+            drop(info_value);
+            for (clone, offset, init_bit_offset) in slots {
+                unsafe {
+                    clone(chunk.slot_ptr(offset), res_chunk.ptr.slot_ptr(offset));
+                }
+                res_chunk.ptr.mark_init(init_bit_offset);
+            }
+            drop(pin);
+            let mut res_chunks = lock_ignore_poison(&res.chunks);
+            res.insert_chunk(&mut res_chunks, chunk_id, res_chunk);
         }
-        return Err(lo);
+        res
     }
 }
 
-/// Describes a chunk within [`PropertyData`].
+impl Drop for RawPropertyData {
+    fn drop(&mut self) {
+        let table = *self.table.get_mut();
+        if !table.is_null() {
+            drop(unsafe { Box::from_raw(table) });
+        }
+    }
+}
+
+/// Owns a chunk within [`PropertyData`].
 struct Chunk {
-    id: usize,
     info: Arc<Mutex<ChunkInfo>>,
-    init_word: usize,
-    ptr: NonNull<u8>,
+    ptr: ChunkPtr,
 }
 
 impl Chunk {
     fn new(info: &Arc<Mutex<ChunkInfo>>) -> Self {
-        let info_value = info.lock().unwrap();
+        let mut info_value = lock_ignore_poison(info);
         unsafe {
             match NonNull::new(alloc(info_value.layout)) {
-                Some(ptr) => Chunk {
-                    id: info_value.id,
-                    info: info.clone(),
-                    init_word: 0,
-                    ptr,
-                },
+                Some(ptr) => {
+                    let init_words = ptr.as_ptr().cast::<AtomicUsize>();
+                    for index in 0..info_value.init_words {
+                        ptr::write(init_words.add(index), AtomicUsize::new(0));
+                    }
+                    info_value.instances.insert(ptr.as_ptr() as usize);
+                    Chunk {
+                        info: info.clone(),
+                        ptr: ChunkPtr(ptr),
+                    }
+                }
                 None => handle_alloc_error(info_value.layout),
             }
         }
     }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        // Once this instance is removed, properties which are dropped won't access it, so its
+        // values can be dropped without holding the lock
+        let mut info = lock_ignore_poison(&self.info);
+        let ptr = self.ptr.0.as_ptr();
+        info.instances.remove(&(ptr as usize));
+        let values = info
+            .props
+            .iter()
+            .filter(|slot| self.ptr.is_init(slot.init_bit_offset))
+            .filter_map(|slot| Some((slot.drop?, slot.offset)))
+            .collect::<Vec<_>>();
+        let layout = info.layout;
+        drop(info);
+        for (drop, offset) in values {
+            unsafe {
+                drop(self.ptr.slot_ptr(offset));
+            }
+        }
+        unsafe {
+            dealloc(ptr, layout);
+        }
+    }
+}
+
+/// A pointer to a live chunk within [`PropertyData`]. The chunk begins with a bitset of
+/// initialization words, followed by the property values.
+#[derive(Clone, Copy)]
+struct ChunkPtr(NonNull<u8>);
+
+impl ChunkPtr {
+    /// Gets the word containing the given initialization bit in this chunk. This may be modified
+    /// concurrently when a [`Property`] is dropped.
+    fn init_word(&self, init_bit_offset: usize) -> &AtomicUsize {
+        unsafe {
+            let init_words = self.0.as_ptr().cast::<AtomicUsize>();
+            &*init_words.add(init_bit_offset / INIT_WORD_BITS)
+        }
+    }
+
+    /// Determines whether the property with the given initialization bit has been initialized.
+    fn is_init(&self, init_bit_offset: usize) -> bool {
+        let init_mask = 1 << (init_bit_offset % INIT_WORD_BITS);
+        (self.init_word(init_bit_offset).load(Ordering::Acquire) & init_mask) > 0
+    }
 
-    /// Attempts to get a reference to a pre-initialized property in this chunk, returning
+    /// Marks the property with the given initialization bit as initialized.
+    fn mark_init(&self, init_bit_offset: usize) {
+        let init_mask = 1 << (init_bit_offset % INIT_WORD_BITS);
+        self.init_word(init_bit_offset)
+            .fetch_or(init_mask, Ordering::AcqRel);
+    }
+
+    /// Marks the property with the given initialization bit as uninitialized, returning whether
+    /// it was previously initialized.
+    fn clear_init(&self, init_bit_offset: usize) -> bool {
+        let init_mask = 1 << (init_bit_offset % INIT_WORD_BITS);
+        let init_word = self.init_word(init_bit_offset);
+        (init_word.fetch_and(!init_mask, Ordering::AcqRel) & init_mask) > 0
+    }
+
+    /// Attempts to get a pointer to a pre-initialized property in this chunk, returning
     /// [`None`] if the the property has not been initialized yet.
-    unsafe fn try_get_mut<P>(&mut self, offset: usize, init_bit_offset: usize) -> Option<&mut P> {
-        let mut ptr = NonNull::new_unchecked(self.ptr.as_ptr().add(offset)).cast::<P>();
-        if (self.init_word & (1 << init_bit_offset)) > 0 {
-            return Some(ptr.as_mut());
+    unsafe fn try_get_ptr<P>(&self, info: &PropertyInfo) -> Option<NonNull<P>> {
+        if self.is_init(info.init_bit_offset) {
+            Some(self.value_ptr(info))
         } else {
-            return None;
+            None
         }
     }
 
-    /// Attempts to get a reference to a property in this chunk, using [`init_value`] to initialize
-    /// it if it isn't initialized yet.
-    unsafe fn get_mut_with_init<P>(
-        &mut self,
-        offset: usize,
-        init_bit_offset: usize,
-        init_value: P,
-    ) -> &mut P {
-        let mut ptr = NonNull::new_unchecked(self.ptr.as_ptr().add(offset)).cast::<P>();
-        if (self.init_word & (1 << init_bit_offset)) == 0 {
-            self.init_word |= 1 << init_bit_offset;
-            ptr::write(ptr.as_ptr(), init_value);
-        }
-        return ptr.as_mut();
+    /// Initializes an uninitialized property in this chunk.
+    unsafe fn init<P>(&self, info: &PropertyInfo, value: P) {
+        slot::write(self.slot_ptr(info.offset), info.storage, value);
+        self.mark_init(info.init_bit_offset);
     }
 
     /// Sets the value of a property in this chunk.
-    unsafe fn set<P>(&mut self, offset: usize, init_bit_offset: usize, value: P) {
-        let mut ptr = NonNull::new_unchecked(self.ptr.as_ptr().add(offset)).cast::<P>();
-        if (self.init_word & (1 << init_bit_offset)) == 0 {
-            self.init_word |= 1 << init_bit_offset;
-            ptr::write(ptr.as_ptr(), value);
+    unsafe fn set<P>(&self, info: &PropertyInfo, value: P) {
+        if !self.is_init(info.init_bit_offset) {
+            self.init(info, value);
+        } else if let Storage::Shared(_) = info.storage {
+            // Replace the allocation instead of modifying it, since it may be shared
+            let slot = self.slot_ptr(info.offset);
+            let old_value = Arc::from_raw(slot::read_ptr::<P>(slot).as_ptr());
+            let value = Arc::into_raw(Arc::new(value)) as *mut P;
+            slot::write_ptr(slot, NonNull::new_unchecked(value));
+            drop(old_value);
         } else {
-            *ptr.as_mut() = value;
+            *self.value_ptr::<P>(info).as_ptr() = value;
         }
     }
-}
 
-impl Drop for Chunk {
-    fn drop(&mut self) {
-        let info = self.info.lock().unwrap();
-        for drop_prop in info.drop_props.iter() {
-            if (self.init_word & (1 << drop_prop.init_bit_offset)) > 0 {
-                unsafe {
-                    let ptr = self.ptr.as_ptr().add(drop_prop.offset);
-                    (drop_prop.drop)(NonNull::new_unchecked(ptr));
+    /// Sets the value of a property in this chunk, moving the previous value out of it.
+    unsafe fn replace<P>(&self, info: &PropertyInfo, value: P) -> Option<P> {
+        if !self.is_init(info.init_bit_offset) {
+            self.init(info, value);
+            return None;
+        }
+        let slot = self.slot_ptr(info.offset);
+        Some(match info.storage {
+            Storage::Direct => mem::replace(&mut *slot.cast::<P>().as_ptr(), value),
+            Storage::Boxed => mem::replace(&mut *slot::read_ptr::<P>(slot).as_ptr(), value),
+            Storage::Shared(make_unique) => {
+                // Swap the allocation rather than writing into it, since it may be shared. The
+                // previous value is only cloned if another object still refers to it.
+                let mut old_ptr = slot::read_ptr::<P>(slot);
+                let value = Arc::into_raw(Arc::new(value)) as *mut P;
+                slot::write_ptr(slot, NonNull::new_unchecked(value));
+                make_unique(NonNull::from(&mut old_ptr).cast());
+                match Arc::try_unwrap(Arc::from_raw(old_ptr.as_ptr())) {
+                    Ok(value) => value,
+                    Err(_) => unreachable!(),
                 }
             }
+        })
+    }
+
+    /// Moves the value of a property out of this chunk, marking it as uninitialized.
+    unsafe fn take<P>(&self, info: &PropertyInfo) -> Option<P> {
+        if self.clear_init(info.init_bit_offset) {
+            let slot = self.slot_ptr(info.offset);
+            Some(match info.storage {
+                Storage::Direct => ptr::read(slot.cast::<P>().as_ptr()),
+                Storage::Boxed => *Box::from_raw(slot::read_ptr::<P>(slot).as_ptr()),
+                Storage::Shared(make_unique) => {
+                    make_unique(slot);
+                    match Arc::try_unwrap(Arc::from_raw(slot::read_ptr::<P>(slot).as_ptr())) {
+                        Ok(value) => value,
+                        Err(_) => unreachable!(),
+                    }
+                }
+            })
+        } else {
+            None
         }
-        unsafe {
-            dealloc(self.ptr.as_ptr(), info.layout);
+    }
+
+    /// Gets a pointer to the value of an initialized property in this chunk, for reading.
+    unsafe fn value_ptr<P>(&self, info: &PropertyInfo) -> NonNull<P> {
+        self.slot_value_ptr(info.offset, info.storage).cast::<P>()
+    }
+
+    /// Gets a pointer to the value of an initialized property in the slot at the given offset.
+    unsafe fn slot_value_ptr(&self, offset: usize, storage: Storage) -> NonNull<u8> {
+        let slot = self.slot_ptr(offset);
+        match storage {
+            Storage::Direct => slot,
+            Storage::Boxed | Storage::Shared(_) => slot::read_ptr(slot),
+        }
+    }
+
+    /// Gets a pointer to the value of an initialized property in this chunk, for mutation.
+    unsafe fn value_ptr_mut<P>(&self, info: &PropertyInfo) -> NonNull<P> {
+        if let Storage::Shared(make_unique) = info.storage {
+            make_unique(self.slot_ptr(info.offset));
         }
+        self.value_ptr(info)
+    }
+
+    /// Gets a pointer to the slot at the given offset in this chunk.
+    unsafe fn slot_ptr(&self, offset: usize) -> NonNull<u8> {
+        NonNull::new_unchecked(self.0.as_ptr().add(offset))
     }
 }
@@ -0,0 +1,178 @@
+//! Storage for the results of functions memoized with [`memoize`] which take additional arguments.
+use crate::*;
+use std::any::Any;
+use std::hash::Hash;
+
+/// A map of memoized results for one object, keyed by the additional arguments of a function
+/// memoized with [`memoize`]. When a capacity is given, the least recently used result is evicted
+/// to make room for a new one.
+///
+/// ## Example
+///
+/// ```
+/// use dynprops::{memoize, Dynamic};
+///
+/// #[memoize(capacity = 2)]
+/// fn scale(obj: &Dynamic, factor: u32) -> u32 {
+///     factor * 10
+/// }
+///
+/// let obj = Dynamic::new();
+/// assert_eq!(scale(&obj, 1), 10);
+/// assert_eq!(scale(&obj, 2), 20);
+/// assert_eq!(scale(&obj, 3), 30);
+/// let results = scale_property().get(&obj);
+/// assert_eq!(results.len(), 2);
+/// assert!(!results.contains_key(&(1,)));
+/// ```
+pub struct MemoMap<K, V> {
+    capacity: Option<usize>,
+    state: Mutex<MemoState<K, V>>,
+}
+
+struct MemoState<K, V> {
+    /// The entries of the map, along with the time they were last used.
+    entries: HashMap<K, (V, u64)>,
+    time: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> MemoMap<K, V> {
+    /// Creates a new, empty map, which will hold at most `capacity` results if given.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is `Some(0)`.
+    pub fn new(capacity: Option<usize>) -> Self {
+        assert!(capacity != Some(0), "Capacity must be non-zero");
+        MemoMap {
+            capacity,
+            state: Mutex::new(MemoState {
+                entries: HashMap::new(),
+                time: 0,
+            }),
+        }
+    }
+
+    /// Gets the maximum number of results this map will hold, if limited.
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Gets the number of results in this map.
+    pub fn len(&self) -> usize {
+        lock_ignore_poison(&self.state).entries.len()
+    }
+
+    /// Determines whether this map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Determines whether this map has a result for the given key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        lock_ignore_poison(&self.state).entries.contains_key(key)
+    }
+
+    /// Gets a copy of the result for the given key, if there is one.
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let mut state = lock_ignore_poison(&self.state);
+        state.time += 1;
+        let time = state.time;
+        let (value, last_used) = state.entries.get_mut(key)?;
+        *last_used = time;
+        Some(value.clone())
+    }
+
+    /// Gets a copy of the result for the given key, using `f` to compute it if there is none.
+    /// The map is not locked while `f` is running, so `f` may use the map recursively.
+    pub fn get_or_insert_with(&self, key: K, f: impl FnOnce() -> V) -> V
+    where
+        V: Clone,
+    {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        let value = f();
+        self.insert(key, value.clone());
+        value
+    }
+
+    /// Stores a result for the given key, returning the previous result for the key, if any.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let mut state = lock_ignore_poison(&self.state);
+        state.time += 1;
+        let time = state.time;
+        if let Some(capacity) = self.capacity {
+            if state.entries.len() >= capacity && !state.entries.contains_key(&key) {
+                let oldest = state
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, (_, last_used))| *last_used)
+                    .map(|(key, _)| key.clone())
+                    .unwrap();
+                state.entries.remove(&oldest);
+            }
+        }
+        let old = state.entries.insert(key, (value, time));
+        old.map(|(value, _)| value)
+    }
+
+    /// Removes the result for the given key, returning it if there was one.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut state = lock_ignore_poison(&self.state);
+        state.entries.remove(key).map(|(value, _)| value)
+    }
+
+    /// Removes all results from this map.
+    pub fn clear(&self) {
+        lock_ignore_poison(&self.state).entries.clear()
+    }
+}
+
+/// Stores the backing [`Property`] of a method memoized with [`memoize`]. Since a `static` can't
+/// depend on `Self`, this keeps a separate property for each type the method is instantiated
+/// with.
+#[doc(hidden)]
+pub struct PropertyRegistry {
+    props: TypeTable<&'static (dyn Any + Send + Sync)>,
+}
+
+impl PropertyRegistry {
+    pub const fn new() -> Self {
+        PropertyRegistry {
+            props: TypeTable::new(),
+        }
+    }
+
+    /// Gets the property for the given object and value types, using `init` to create it if
+    /// needed. Properties in the registry are never dropped.
+    pub fn get_or_init<T, P>(
+        &self,
+        init: impl FnOnce() -> Property<T, P>,
+    ) -> &'static Property<T, P>
+    where
+        T: Extend + 'static,
+        P: Send + Sync + 'static,
+    {
+        let type_id = TypeId::of::<Property<T, P>>();
+        let prop = match self.props.get(type_id) {
+            Some(prop) => prop,
+            None => {
+                // Don't hold the lock while creating the property, since that locks the subject
+                let prop = init();
+                self.props
+                    .get_or_insert_with(type_id, || Box::leak(Box::new(prop)))
+            }
+        };
+        prop.downcast_ref::<Property<T, P>>().unwrap()
+    }
+}
+
+impl Default for PropertyRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
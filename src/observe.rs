@@ -0,0 +1,202 @@
+//! Change observers for [`Property`] values.
+use crate::*;
+
+/// Identifies an observer registered with [`Property::observe`] or [`Property::observe_object`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+/// A callback which is given an object, the previous value of a property on it (or [`None`] if it
+/// was uninitialized), and the new value of the property.
+type ObserverFn<T, P> = dyn Fn(&T, Option<&P>, &P) + Send + Sync;
+
+type ObserverList<T, P> = Vec<(ObserverId, Arc<ObserverFn<T, P>>)>;
+
+/// The observers registered for a [`Property`].
+pub(crate) struct Observers<T: Extend, P> {
+    state: Mutex<ObserverState<T, P>>,
+
+    /// The property used to store observers for specific objects. This is only allocated once
+    /// an object-specific observer is registered.
+    objects: OnceLock<ObjectObservers<T, P>>,
+}
+
+struct ObserverState<T, P> {
+    next_id: u64,
+    global: ObserverList<T, P>,
+
+    /// Copies a value of the property, so that the old value can be provided to observers after
+    /// it is mutated through [`Property::get_mut`].
+    clone: Option<fn(&P) -> P>,
+}
+
+/// A hidden property which stores the observers for specific objects.
+struct ObjectObservers<T: Extend, P> {
+    info: PropertyInfo,
+    _phantom: PhantomData<fn(&T, &P)>,
+}
+
+impl<T: Extend, P> Observers<T, P> {
+    pub fn new() -> Self {
+        Observers {
+            state: Mutex::new(ObserverState {
+                next_id: 0,
+                global: Vec::new(),
+                clone: None,
+            }),
+            objects: OnceLock::new(),
+        }
+    }
+
+    /// Determines whether there are any observers for changes to the given object. This doesn't
+    /// need to lock anything, since the caller has exclusive access to the property.
+    pub fn is_observed(&mut self, obj: &T) -> bool {
+        !self.state.get_mut().unwrap().global.is_empty()
+            || self
+                .objects
+                .get()
+                .and_then(|objects| objects.try_list(obj))
+                .is_some_and(|list| !list.lock().unwrap().is_empty())
+    }
+
+    /// Makes a copy of a value so that it can be provided as the old value to observers.
+    pub fn snapshot(&self, value: &P) -> P {
+        let clone = self.state.lock().unwrap().clone.unwrap();
+        clone(value)
+    }
+
+    /// Calls all observers which apply to the given object. Locks are not held while calling
+    /// observers, so observers may register or unregister other observers.
+    pub fn notify(&self, obj: &T, old: Option<&P>, new: &P) {
+        let mut observers = self
+            .state
+            .lock()
+            .unwrap()
+            .global
+            .iter()
+            .map(|(_, observer)| observer.clone())
+            .collect::<Vec<_>>();
+        if let Some(list) = self.objects.get().and_then(|objects| objects.try_list(obj)) {
+            let list = list.lock().unwrap();
+            observers.extend(list.iter().map(|(_, observer)| observer.clone()));
+        }
+        for observer in observers {
+            observer(obj, old, new);
+        }
+    }
+
+    fn next_id(state: &mut ObserverState<T, P>) -> ObserverId {
+        let id = ObserverId(state.next_id);
+        state.next_id += 1;
+        id
+    }
+}
+
+impl<T: Extend, P> ObjectObservers<T, P> {
+    /// Gets the list of observers for the given object, creating it if needed.
+    fn list<'a>(&'a self, obj: &'a T) -> &'a Mutex<ObserverList<T, P>> {
+        unsafe {
+            obj.prop_data()
+                .source
+                .get(&self.info, || Mutex::new(Vec::new()))
+        }
+    }
+
+    /// Gets the list of observers for the given object, or [`None`] if none were ever registered.
+    fn try_list<'a>(&'a self, obj: &'a T) -> Option<&'a Mutex<ObserverList<T, P>>> {
+        unsafe { obj.prop_data().source.try_get(&self.info) }
+    }
+}
+
+impl<T: Extend, P> Drop for ObjectObservers<T, P> {
+    fn drop(&mut self) {
+        let (_, values) = lock_ignore_poison(&self.info.chunk).free_prop(&self.info);
+        drop(values);
+    }
+}
+
+impl<T: Extend, P: Send + Clone + 'static> Property<T, P> {
+    /// Registers a callback which is called after the value of this property is changed on any
+    /// object, either by [`Property::set`] or by mutating it through [`Property::get_mut`]. The
+    /// callback is given the object, the previous value (or [`None`] if the property was not
+    /// initialized), and the new value.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use dynprops::{Dynamic, Property};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let mut pressure = Property::<Dynamic, f32>::new();
+    /// let log = Arc::new(Mutex::new(Vec::new()));
+    /// let log_inner = log.clone();
+    /// pressure.observe(move |_, old, new| log_inner.lock().unwrap().push((old.copied(), *new)));
+    /// let obj = Dynamic::new();
+    /// pressure.set(&obj, 30.0);
+    /// *pressure.get_mut(&obj) += 2.0;
+    /// assert_eq!(*log.lock().unwrap(), [(None, 30.0), (Some(30.0), 32.0)]);
+    /// ```
+    pub fn observe(
+        &self,
+        observer: impl Fn(&T, Option<&P>, &P) + Send + Sync + 'static,
+    ) -> ObserverId {
+        let mut state = self.observers.state.lock().unwrap();
+        state.clone = Some(P::clone);
+        let id = Observers::next_id(&mut state);
+        state.global.push((id, Arc::new(observer)));
+        id
+    }
+
+    /// Registers a callback which is called after the value of this property is changed on the
+    /// given object. See [`Property::observe`] for details. The callback will be dropped along
+    /// with the object, and it will not apply to clones of the object.
+    pub fn observe_object(
+        &self,
+        obj: &T,
+        observer: impl Fn(&T, Option<&P>, &P) + Send + Sync + 'static,
+    ) -> ObserverId
+    where
+        T: 'static,
+    {
+        let mut state = self.observers.state.lock().unwrap();
+        state.clone = Some(P::clone);
+        let id = Observers::next_id(&mut state);
+        drop(state);
+        let objects = self.observers.objects.get_or_init(|| ObjectObservers {
+            info: T::subject().alloc_prop(&PropertyType::hidden::<Mutex<ObserverList<T, P>>>()),
+            _phantom: PhantomData,
+        });
+        let list = objects.list(obj);
+        list.lock().unwrap().push((id, Arc::new(observer)));
+        id
+    }
+}
+
+impl<T: Extend, P> Property<T, P> {
+    /// Unregisters an observer that was registered using [`Property::observe`]. Returns `false`
+    /// if there was no such observer.
+    pub fn unobserve(&self, id: ObserverId) -> bool {
+        let mut state = self.observers.state.lock().unwrap();
+        let len = state.global.len();
+        state.global.retain(|(observer_id, _)| *observer_id != id);
+        state.global.len() < len
+    }
+
+    /// Unregisters an observer that was registered for the given object using
+    /// [`Property::observe_object`]. Returns `false` if there was no such observer.
+    pub fn unobserve_object(&self, obj: &T, id: ObserverId) -> bool {
+        let list = self
+            .observers
+            .objects
+            .get()
+            .and_then(|objects| objects.try_list(obj));
+        match list {
+            Some(list) => {
+                let mut list = list.lock().unwrap();
+                let len = list.len();
+                list.retain(|(observer_id, _)| *observer_id != id);
+                list.len() < len
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Savepoints, which record changes to the property values of an object so that they can be
+//! rolled back.
+use crate::*;
+use std::sync::Weak;
+
+/// The changes recorded for an object while it has active [`Savepoint`]s.
+pub(crate) struct Journal {
+    /// The active savepoints for the object, in the order they were created, along with the
+    /// number of changes that were recorded before each of them.
+    savepoints: Vec<(Weak<()>, usize)>,
+    changes: Vec<Change>,
+}
+
+/// A change to a property value on an object, which can be undone by restoring its previous
+/// value.
+struct Change {
+    info: PropertyInfo,
+
+    /// Whether the previous value was recorded. This is not the case when a value of a property
+    /// which isn't transactional is mutated or taken.
+    restorable: bool,
+
+    /// The boxed previous value, or [`None`] if the property was uninitialized.
+    old: Option<NonNull<u8>>,
+    restore: unsafe fn(&RawPropertyData, &PropertyInfo, Option<NonNull<u8>>),
+    drop: unsafe fn(NonNull<u8>),
+}
+
+// Previous values are only recorded through a `Property`, which requires its values to be `Send`
+unsafe impl Send for Change {}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal {
+            savepoints: Vec::new(),
+            changes: Vec::new(),
+        }
+    }
+
+    /// Removes savepoints which were committed, returning the changes which no longer need to
+    /// be kept. These should be dropped after the journal is unlocked, since dropping a value may
+    /// access the object.
+    fn prune(&mut self) -> Vec<Change> {
+        self.savepoints
+            .retain(|(savepoint, _)| savepoint.strong_count() > 0);
+        if self.savepoints.is_empty() {
+            mem::take(&mut self.changes)
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+impl Change {
+    fn new<P>(info: &PropertyInfo, old: Option<P>) -> Self {
+        Change {
+            info: info.clone(),
+            restorable: true,
+            old: old
+                .map(|old| unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(old))) }.cast()),
+            restore: restore::<P>,
+            drop: drop_boxed::<P>,
+        }
+    }
+
+    /// Describes a change whose previous value wasn't recorded, so it can't be undone.
+    fn unrestorable<P>(info: &PropertyInfo) -> Self {
+        let mut change = Self::new::<P>(info, None);
+        change.restorable = false;
+        change
+    }
+
+    /// Restores the previous value of the property on the given object.
+    unsafe fn undo(mut self, data: &RawPropertyData) {
+        let old = self.old.take();
+        (self.restore)(data, &self.info, old)
+    }
+}
+
+impl Drop for Change {
+    fn drop(&mut self) {
+        if let Some(old) = self.old {
+            unsafe { (self.drop)(old) }
+        }
+    }
+}
+
+unsafe fn restore<P>(data: &RawPropertyData, info: &PropertyInfo, old: Option<NonNull<u8>>) {
+    let mut old = old.map(|old| *Box::from_raw(old.cast::<P>().as_ptr()));
+    let mut current = None;
+
+    // Hold the chunk info lock so that the property can't be dropped while its value is restored
+    let chunk_info = lock_ignore_poison(&info.chunk);
+    if chunk_info.props.iter().any(|slot| slot.id == info.slot_id) {
+        if let Some(chunk) = data.find_chunk(info.chunk_id) {
+            current = chunk.take::<P>(info);
+            if let Some(old) = old.take() {
+                chunk.init(info, old);
+            }
+        }
+    }
+    drop(chunk_info);
+    drop(current);
+}
+
+unsafe fn drop_boxed<P>(value: NonNull<u8>) {
+    drop(Box::from_raw(value.cast::<P>().as_ptr()));
+}
+
+impl RawPropertyData {
+    /// Determines whether changes to property values in this [`RawPropertyData`] are being
+    /// recorded, i.e. whether there are any active [`Savepoint`]s for it.
+    pub(crate) fn is_recording(&self) -> bool {
+        let mut journal = lock_ignore_poison(&self.journal);
+        let discarded = journal.prune();
+        let is_recording = !journal.savepoints.is_empty();
+        drop(journal);
+        drop(discarded);
+        is_recording
+    }
+
+    /// Records the previous value of a property which was changed, if there are any active
+    /// [`Savepoint`]s for this [`RawPropertyData`]. Otherwise, the value is dropped.
+    pub(crate) unsafe fn record<P>(&self, info: &PropertyInfo, old: Option<P>) {
+        let mut journal = lock_ignore_poison(&self.journal);
+        let discarded = journal.prune();
+        let old = if journal.savepoints.is_empty() {
+            old
+        } else {
+            journal.changes.push(Change::new(info, old));
+            None
+        };
+        drop(journal);
+        drop((discarded, old));
+    }
+
+    /// Records that a property was changed without recording its previous value, if there are
+    /// any active [`Savepoint`]s for this [`RawPropertyData`]. Rolling back the change will
+    /// report the property in a [`RollbackError`].
+    pub(crate) fn record_unrestorable<P>(&self, info: &PropertyInfo) {
+        let mut journal = lock_ignore_poison(&self.journal);
+        let discarded = journal.prune();
+        if !journal.savepoints.is_empty() {
+            journal.changes.push(Change::unrestorable::<P>(info));
+        }
+        drop(journal);
+        drop(discarded);
+    }
+}
+
+/// Records changes made to the property values of an object, so that they can be rolled back.
+///
+/// While a savepoint is active, the previous value (or uninitialized state) of a property is
+/// recorded whenever it is changed through [`Property::set`], [`Property::get_mut`],
+/// [`Property::take`] or [`Property::unset`]. Mutating or taking a value requires a copy of it, so
+/// it can only be restored for properties which were made
+/// [transactional](Property::transactional). For other properties, rolling back such a change
+/// leaves the current value in place and reports the property in a [`RollbackError`].
+/// Initializing a value when it is first accessed is not considered a change.
+///
+/// Dropping a savepoint, or calling [`Savepoint::commit`], keeps the changes. Savepoints may be
+/// nested, and rolling back an outer savepoint undoes changes made under inner savepoints, even
+/// if they were committed. To update several objects atomically, create a savepoint for each of
+/// them and roll back all of them if the update fails. Since rolling back requires exclusive
+/// access to each object, the intermediate state can't be observed.
+///
+/// ## Example
+///
+/// ```
+/// use dynprops::{Dynamic, Property, Savepoint};
+///
+/// let mut name = Property::<Dynamic, &str>::new();
+/// let mut count = Property::<Dynamic, u32>::new().transactional();
+/// let mut obj = Dynamic::new();
+/// name.set(&obj, "Foo");
+/// let savepoint = Savepoint::new(&obj);
+/// name.set(&obj, "Bar");
+/// *count.get_mut(&obj) += 1;
+/// savepoint.rollback(&mut obj).unwrap();
+/// assert_eq!(*name.get(&obj), "Foo");
+/// assert!(!count.is_set(&obj));
+/// ```
+pub struct Savepoint {
+    token: Arc<()>,
+}
+
+impl Savepoint {
+    /// Begins recording changes to the property values of the given object.
+    pub fn new<T: Extend>(obj: &T) -> Self {
+        let data = &obj.prop_data().source;
+        let token = Arc::new(());
+        let mut journal = lock_ignore_poison(&data.journal);
+        let discarded = journal.prune();
+        let len = journal.changes.len();
+        journal.savepoints.push((Arc::downgrade(&token), len));
+        drop(journal);
+        drop(discarded);
+        Savepoint { token }
+    }
+
+    /// Keeps the changes made since this savepoint was created. This is equivalent to dropping
+    /// the savepoint.
+    pub fn commit(self) {}
+
+    /// Restores the property values of the given object to what they were when this savepoint
+    /// was created. Savepoints created after this one can no longer be rolled back. Observers are
+    /// not notified of restored values.
+    ///
+    /// Returns an error listing the properties whose previous values weren't recorded, because
+    /// they were mutated or taken without being [transactional](Property::transactional). Those
+    /// properties keep their current values, and all other properties are restored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this savepoint was not created for the given object, or if a savepoint created
+    /// before it was rolled back.
+    pub fn rollback<T: Extend>(self, obj: &mut T) -> Result<(), RollbackError> {
+        let obj = &*obj;
+        let data = &obj.prop_data().source;
+        let mut journal = lock_ignore_poison(&data.journal);
+        let index = journal
+            .savepoints
+            .iter()
+            .position(|(savepoint, _)| savepoint.as_ptr() == Arc::as_ptr(&self.token));
+        let index = match index {
+            Some(index) => index,
+            None => {
+                drop(journal);
+                panic!("Savepoint does not apply to this object");
+            }
+        };
+        let len = journal.savepoints[index].1;
+        journal.savepoints.truncate(index);
+        let changes = journal.changes.split_off(len);
+        drop(self);
+        let discarded = journal.prune();
+        drop(journal);
+        drop(discarded);
+
+        // Exclusive access to the object ensures that no references to the values exist. A
+        // property is only unrestored if no earlier change to it could be undone.
+        let mut unrestored: Vec<PropertyInfo> = Vec::new();
+        for change in changes.into_iter().rev() {
+            unrestored.retain(|info| info.slot_id != change.info.slot_id);
+            if change.restorable {
+                computed::invalidate(obj, &change.info);
+                unsafe { change.undo(data) };
+            } else {
+                unrestored.push(change.info.clone());
+            }
+        }
+        let properties: Vec<PropertyDescriptor> = unrestored
+            .iter()
+            .filter_map(PropertyInfo::descriptor)
+            .collect();
+        if properties.is_empty() {
+            Ok(())
+        } else {
+            Err(RollbackError { properties })
+        }
+    }
+}
+
+/// The error returned by [`Savepoint::rollback`] when the previous values of some properties
+/// weren't recorded, so they couldn't be restored.
+#[derive(Debug)]
+pub struct RollbackError {
+    properties: Vec<PropertyDescriptor>,
+}
+
+impl RollbackError {
+    /// Describes the properties which couldn't be restored.
+    pub fn properties(&self) -> &[PropertyDescriptor] {
+        &self.properties
+    }
+}
+
+impl fmt::Display for RollbackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} property value(s) could not be restored",
+            self.properties.len()
+        )
+    }
+}
+
+impl std::error::Error for RollbackError {}
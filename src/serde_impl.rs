@@ -0,0 +1,304 @@
+//! Serialization support for [`PropertyData`], enabled by the `serde` feature.
+use crate::*;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Describes how to serialize and deserialize the values of a property in a chunk.
+#[derive(Clone)]
+pub(crate) struct SerdeInfo {
+    key: Arc<str>,
+    serialize: SerializeFn,
+    deserialize: DeserializeFn,
+}
+
+type SerializeFn = unsafe fn(NonNull<u8>) -> &'static dyn erased_serde::Serialize;
+
+type DeserializeFn = unsafe fn(
+    &mut dyn erased_serde::Deserializer,
+    NonNull<u8>,
+    Storage,
+) -> Result<(), erased_serde::Error>;
+
+/// A value for an unrecognized key that was preserved during deserialization.
+pub(crate) type UnknownValue = Arc<dyn erased_serde::Serialize + Send + Sync>;
+
+/// Gets a value of type `P`, given a pointer to it. The returned reference must not outlive the
+/// value.
+unsafe fn serialize<P: Serialize + 'static>(
+    value: NonNull<u8>,
+) -> &'static dyn erased_serde::Serialize {
+    &*value.cast::<P>().as_ptr()
+}
+
+/// Deserializes a value of type `P` into an uninitialized slot.
+unsafe fn deserialize<P: DeserializeOwned>(
+    deserializer: &mut dyn erased_serde::Deserializer,
+    slot: NonNull<u8>,
+    storage: Storage,
+) -> Result<(), erased_serde::Error> {
+    let value = erased_serde::deserialize::<P>(deserializer)?;
+    slot::write(slot, storage, value);
+    Ok(())
+}
+
+impl<T: Extend, P: Send + Sync + Serialize + DeserializeOwned + 'static> Property<T, P> {
+    /// Registers this property under the given key, so that its values are included when the
+    /// [`PropertyData`] for an object is serialized, and restored when it is deserialized. The key
+    /// should be stable across runs of the program.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another live property on the same [`Subject`] is already registered with `key`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use dynprops::{Extend, Property, PropertyData};
+    ///
+    /// #[derive(Extend)]
+    /// struct Thing {
+    ///     #[prop_data]
+    ///     prop_data: PropertyData<Thing>,
+    /// }
+    ///
+    /// let mut pressure = Property::<Thing, f32>::new().with_key("pressure");
+    /// let thing = Thing { prop_data: PropertyData::new() };
+    /// pressure.set(&thing, 32.5);
+    /// let json = serde_json::to_string(&thing.prop_data).unwrap();
+    /// assert_eq!(json, r#"{"pressure":32.5}"#);
+    /// let copy = Thing { prop_data: serde_json::from_str(&json).unwrap() };
+    /// assert_eq!(pressure.try_get(&copy), Some(&32.5));
+    /// ```
+    pub fn with_key(self, key: impl Into<Arc<str>>) -> Self {
+        let key = key.into();
+        let subject = T::subject().info.lock().unwrap();
+        if subject.find_key(&key).is_some() {
+            drop(subject);
+            panic!("Property key {:?} is already in use", key);
+        }
+        let mut chunk = lock_ignore_poison(&self.info.chunk);
+        let slot = chunk.slot_mut(self.info.init_bit_offset);
+        slot.serde = Some(SerdeInfo {
+            key,
+            serialize: serialize::<P>,
+            deserialize: deserialize::<P>,
+        });
+        drop(chunk);
+        drop(subject);
+        self
+    }
+}
+
+impl SubjectInfo {
+    /// Finds the chunk and initialization bit of the property registered with the given key.
+    fn find_key(&self, key: &str) -> Option<(&Arc<Mutex<ChunkInfo>>, usize)> {
+        self.open_chunks.iter().find_map(|chunk| {
+            let info = lock_ignore_poison(chunk);
+            let slot = info.props.iter().find(|slot| match &slot.serde {
+                Some(serde) => &*serde.key == key,
+                None => false,
+            })?;
+            Some((chunk, slot.init_bit_offset))
+        })
+    }
+}
+
+/// Serializes the values of every initialized property which has a key (see
+/// [`Property::with_key`]) as a map. This will block while any property value on the object is
+/// being mutated.
+impl<T> Serialize for PropertyData<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.source.serialize(serializer)
+    }
+}
+
+impl Serialize for RawPropertyData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        /// Serializes a property value using its [`SerializeFn`].
+        struct Value(NonNull<u8>, SerializeFn);
+
+        impl Serialize for Value {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                erased_serde::serialize(unsafe { (self.1)(self.0) }, serializer)
+            }
+        }
+
+        let _guard = self.begin_read_all();
+        let mut map = serializer.serialize_map(None)?;
+        let mut keys = Vec::new();
+        for (info, chunk) in self.chunk_list() {
+            // Values are serialized without holding the chunk info lock, since they may contain
+            // objects which use the same chunk
+            let mut info_value = lock_ignore_poison(&info);
+            let mut entries = Vec::new();
+            for slot in info_value.props.iter() {
+                if let Some(serde) = &slot.serde {
+                    if chunk.is_init(slot.init_bit_offset) {
+                        let value = unsafe { chunk.slot_value_ptr(slot.offset, slot.storage) };
+                        entries.push((serde.key.clone(), Value(value, serde.serialize)));
+                    }
+                }
+            }
+            let _pin = ChunkPin::new(&info, &mut info_value);
+            drop(info_value);
+            for (key, value) in entries {
+                map.serialize_entry(&*key, &value)?;
+                keys.push(key);
+            }
+        }
+
+        // Write back preserved entries, unless they have since been replaced by a property
+        for (key, value) in self.unknown.iter() {
+            if !keys.contains(key) {
+                map.serialize_entry(&**key, &**value)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// Deserializes a map of values for properties registered with [`Property::with_key`]. Keys which
+/// don't correspond to a live property of `T` are reported as an error; use
+/// [`PropertyData::deserialize_preserving`] to keep them instead.
+impl<'de, T: Extend> Deserialize<'de> for PropertyData<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_with::<T, D, ()>(deserializer, false)
+    }
+}
+
+impl<T: Extend> PropertyData<T> {
+    /// Deserializes a map of values for properties registered with [`Property::with_key`]. Entries
+    /// whose keys don't correspond to a live property of `T` are deserialized as `V` (which
+    /// should be a self-describing type such as `serde_json::Value`) and kept, so that they will
+    /// be written back when the [`PropertyData`] is serialized.
+    pub fn deserialize_preserving<'de, V, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        V: Deserialize<'de> + Serialize + Send + Sync + 'static,
+        D: Deserializer<'de>,
+    {
+        deserialize_with::<T, D, V>(deserializer, true)
+    }
+}
+
+/// Deserializes a [`PropertyData`], optionally preserving unknown entries as values of type `V`.
+fn deserialize_with<'de, T, D, V>(
+    deserializer: D,
+    preserve: bool,
+) -> Result<PropertyData<T>, D::Error>
+where
+    T: Extend,
+    D: Deserializer<'de>,
+    V: Deserialize<'de> + Serialize + Send + Sync + 'static,
+{
+    struct DataVisitor<T, V> {
+        preserve: bool,
+        _marker: PhantomData<fn() -> (T, V)>,
+    }
+
+    impl<'de, T, V> Visitor<'de> for DataVisitor<T, V>
+    where
+        T: Extend,
+        V: Deserialize<'de> + Serialize + Send + Sync + 'static,
+    {
+        type Value = PropertyData<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a map of property values")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut res = PropertyData::<T>::new();
+            while let Some(key) = map.next_key::<String>()? {
+                let found = {
+                    let subject = T::subject().info.lock().unwrap();
+                    subject
+                        .find_key(&key)
+                        .map(|(chunk, init_bit_offset)| (chunk.clone(), init_bit_offset))
+                };
+                let (chunk_info, init_bit_offset) = match found {
+                    Some(found) => found,
+                    None if self.preserve => {
+                        let value: V = map.next_value()?;
+                        res.source.unknown.push((key.into(), Arc::new(value)));
+                        continue;
+                    }
+                    None => {
+                        return Err(de::Error::custom(format_args!(
+                            "unknown property key {:?}",
+                            key
+                        )))
+                    }
+                };
+
+                // Create the chunk before locking its info, since that is needed to create it
+                let chunk_id = lock_ignore_poison(&chunk_info).id;
+                let chunk = match res.source.find_chunk(chunk_id) {
+                    Some(chunk) => chunk,
+                    None => {
+                        let mut chunks = lock_ignore_poison(&res.source.chunks);
+                        let chunk = Chunk::new(&chunk_info);
+                        res.source.insert_chunk(&mut chunks, chunk_id, chunk)
+                    }
+                };
+
+                // The property may have been dropped since it was found, so look it up again
+                let mut info = lock_ignore_poison(&chunk_info);
+                let slot = info.props.iter().find(|slot| {
+                    slot.init_bit_offset == init_bit_offset
+                        && matches!(&slot.serde, Some(serde) if *serde.key == *key)
+                });
+                let slot = match slot {
+                    Some(slot) => slot,
+                    None => {
+                        return Err(de::Error::custom(format_args!(
+                            "unknown property key {:?}",
+                            key
+                        )))
+                    }
+                };
+                if chunk.is_init(init_bit_offset) {
+                    return Err(de::Error::custom(format_args!(
+                        "duplicate property key {:?}",
+                        key
+                    )));
+                }
+                let seed = SlotSeed {
+                    slot: unsafe { chunk.slot_ptr(slot.offset) },
+                    storage: slot.storage,
+                    deserialize: slot.serde.as_ref().unwrap().deserialize,
+                };
+
+                // Don't hold the chunk info lock while deserializing, since the value may contain
+                // objects which use the same chunk
+                let _pin = ChunkPin::new(&chunk_info, &mut info);
+                drop(info);
+                map.next_value_seed(seed)?;
+                chunk.mark_init(init_bit_offset);
+            }
+            Ok(res)
+        }
+    }
+
+    deserializer.deserialize_map(DataVisitor::<T, V> {
+        preserve,
+        _marker: PhantomData,
+    })
+}
+
+/// Deserializes a property value into an uninitialized slot using its [`DeserializeFn`].
+struct SlotSeed {
+    slot: NonNull<u8>,
+    storage: Storage,
+    deserialize: DeserializeFn,
+}
+
+impl<'de> DeserializeSeed<'de> for SlotSeed {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
+        unsafe { (self.deserialize)(&mut deserializer, self.slot, self.storage) }
+            .map_err(de::Error::custom)
+    }
+}
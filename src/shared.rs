@@ -0,0 +1,195 @@
+//! Subjects which are shared between multiple types, and properties which apply to all of them.
+use crate::*;
+
+/// Types whose [`Subject`] is the one shared by all types that implement `ExtendShared<K>` (see
+/// [`Subject::shared`]). Properties for such types can be defined once using [`SharedProperty`].
+///
+/// This is typically implemented using `#[derive(Extend)]` along with an
+/// `#[extend(subject = K)]` attribute.
+///
+/// # Safety
+///
+/// [`Extend::subject`] must return [`Subject::shared::<K>()`](Subject::shared).
+pub unsafe trait ExtendShared<K: 'static>: Extend {}
+
+/// Stands in for an object of any type which implements [`ExtendShared<K>`], so that a
+/// [`SharedProperty`] can be implemented using a [`Property`].
+#[repr(transparent)]
+pub(crate) struct SharedObject<K> {
+    prop_data: PropertyData<SharedObject<K>>,
+    _marker: PhantomData<fn() -> K>,
+}
+
+unsafe impl<K: 'static> Extend for SharedObject<K> {
+    fn subject() -> &'static Subject {
+        Subject::of::<Self>()
+    }
+
+    fn prop_data(&self) -> &PropertyData<Self> {
+        &self.prop_data
+    }
+}
+
+impl<K: 'static> SharedObject<K> {
+    /// Gets the [`SharedObject`] for the given object, which has the same [`PropertyData`].
+    fn of<T: ExtendShared<K>>(obj: &T) -> &Self {
+        // SAFETY: `T` uses the same subject as `SharedObject<K>`, and `SharedObject<K>` is a
+        // transparent wrapper around its `PropertyData`
+        unsafe { &*(obj.prop_data().cast::<Self>() as *const PropertyData<Self> as *const Self) }
+    }
+}
+
+impl Subject {
+    /// Gets the subject shared by all types which implement [`ExtendShared<K>`]. `K` is only used
+    /// to identify the subject, and can be any type.
+    pub fn shared<K: 'static>() -> &'static Subject {
+        SharedObject::<K>::subject()
+    }
+}
+
+/// A property which can be used on any type that implements [`ExtendShared<K>`]. Unlike
+/// [`Property`], initializers are not given the object, since its type is not known.
+///
+/// ## Example
+///
+/// ```
+/// use dynprops::{Extend, PropertyData, SharedProperty};
+///
+/// /// Identifies the subject shared by all vehicles.
+/// enum Vehicles {}
+///
+/// #[derive(Extend)]
+/// #[extend(subject = Vehicles)]
+/// struct Car {
+///     #[prop_data]
+///     prop_data: PropertyData<Car>,
+/// }
+///
+/// #[derive(Extend)]
+/// #[extend(subject = Vehicles)]
+/// struct Truck {
+///     #[prop_data]
+///     prop_data: PropertyData<Truck>,
+/// }
+///
+/// let mut mileage = SharedProperty::<Vehicles, u32>::new();
+/// let car = Car { prop_data: PropertyData::new() };
+/// let truck = Truck { prop_data: PropertyData::new() };
+/// mileage.set(&car, 12000);
+/// *mileage.get_mut(&truck) += 300;
+/// assert_eq!(*mileage.get(&car), 12000);
+/// assert_eq!(*mileage.get(&truck), 300);
+/// ```
+pub struct SharedProperty<K: 'static, P> {
+    inner: Property<SharedObject<K>, P>,
+}
+
+impl<K: 'static, P: Send + Default + 'static> SharedProperty<K, P> {
+    /// Creates a new property whose values are initialized to [`Default::default()`].
+    pub fn new() -> Self {
+        Self {
+            inner: Property::new(),
+        }
+    }
+}
+
+impl<K: 'static, P: Send + Sync + Clone + 'static> SharedProperty<K, P> {
+    /// Creates a new property whose values are initialized to a copy of `value`.
+    pub fn with_const_init(value: P) -> Self {
+        Self {
+            inner: Property::with_const_init(value),
+        }
+    }
+
+    /// Sets the [`ClonePolicy`] for this property. See [`Property::with_clone_policy`].
+    pub fn with_clone_policy(self, policy: ClonePolicy) -> Self {
+        Self {
+            inner: self.inner.with_clone_policy(policy),
+        }
+    }
+
+    /// Makes this property transactional. See [`Property::transactional`].
+    pub fn transactional(self) -> Self {
+        Self {
+            inner: self.inner.transactional(),
+        }
+    }
+}
+
+impl<K: 'static, P: Send + Sync + fmt::Debug> SharedProperty<K, P> {
+    /// Gives this property a name. See [`Property::with_name`].
+    pub fn with_name(self, name: impl Into<Arc<str>>) -> Self {
+        Self {
+            inner: self.inner.with_name(name),
+        }
+    }
+}
+
+impl<K: 'static, P: Send> SharedProperty<K, P> {
+    /// Creates a new property whose values are initialized by calling `init`.
+    pub fn with_init(init: impl Fn() -> P + Send + Sync + 'static) -> Self
+    where
+        P: 'static,
+    {
+        Self {
+            inner: Property::with_init(move |_| init()),
+        }
+    }
+
+    /// Gets the value of this property on the given object. If the property has never been
+    /// accessed before, it's value will be initialized using the property's initializer.
+    pub fn get<'a, T: ExtendShared<K>>(&'a self, obj: &'a T) -> &'a P {
+        self.inner.get(SharedObject::of(obj))
+    }
+
+    /// Gets a mutable reference to the value of this property on the given object. If the property
+    /// has never been accessed before, it's value will be initialized using the property's
+    /// initializer.
+    pub fn get_mut<'a, T: ExtendShared<K>>(&'a mut self, obj: &'a T) -> PropertyMut<'a, P> {
+        self.inner.get_mut(SharedObject::of(obj))
+    }
+
+    /// Gets the value of this property on the given object. If the property has never been
+    /// accessed before, it's value will be initialized using `init`.
+    pub fn get_with_init<'a, T: ExtendShared<K>>(
+        &'a self,
+        obj: &'a T,
+        init: impl Fn() -> P,
+    ) -> &'a P {
+        self.inner.get_with_init(SharedObject::of(obj), init)
+    }
+
+    /// Gets the value of this property on the given object, or [`None`] if it has not been
+    /// initialized.
+    pub fn try_get<'a, T: ExtendShared<K>>(&'a self, obj: &'a T) -> Option<&'a P> {
+        self.inner.try_get(SharedObject::of(obj))
+    }
+
+    /// Determines whether this property has been initialized on the given object.
+    pub fn is_set<T: ExtendShared<K>>(&self, obj: &T) -> bool {
+        self.inner.is_set(SharedObject::of(obj))
+    }
+
+    /// Sets the value of this property on the given object.
+    pub fn set<T: ExtendShared<K>>(&mut self, obj: &T, value: P) {
+        self.inner.set(SharedObject::of(obj), value)
+    }
+
+    /// Removes the value of this property from the given object, returning it if it was
+    /// initialized. The property will be reinitialized the next time it is accessed.
+    pub fn take<T: ExtendShared<K>>(&mut self, obj: &T) -> Option<P> {
+        self.inner.take(SharedObject::of(obj))
+    }
+
+    /// Drops the value of this property on the given object, if it was initialized. The property
+    /// will be reinitialized the next time it is accessed.
+    pub fn unset<T: ExtendShared<K>>(&mut self, obj: &T) {
+        self.inner.unset(SharedObject::of(obj))
+    }
+}
+
+impl<K: 'static, P: Send + Default + 'static> Default for SharedProperty<K, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,103 @@
+//! Properties whose values are protected by their own locks, allowing them to be changed through
+//! a shared reference to the property.
+use crate::*;
+use std::sync::{LockResult, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A property whose values are each protected by a separate lock. Unlike [`Property`], values can
+/// be changed through a shared reference to the property, so several threads can update them
+/// concurrently. Since each value has its own lock, holding a lock on one value does not block
+/// access to other properties of the object.
+///
+/// As with [`RwLock`], a lock is poisoned if a thread panics while holding exclusive access to
+/// it. Changes to values invalidate dependent [`ComputedProperty`] values, but they are not
+/// recorded by [`Savepoint`]s.
+///
+/// ## Example
+///
+/// ```
+/// use dynprops::{Dynamic, SyncProperty};
+///
+/// let log = SyncProperty::<Dynamic, Vec<u32>>::new();
+/// let obj = Dynamic::new();
+/// std::thread::scope(|s| {
+///     for i in 0..4 {
+///         let log = &log;
+///         let obj = &obj;
+///         s.spawn(move || log.write(obj).unwrap().push(i));
+///     }
+/// });
+/// let mut log = log.read(&obj).unwrap().clone();
+/// log.sort();
+/// assert_eq!(log, [0, 1, 2, 3]);
+/// ```
+pub struct SyncProperty<T: Extend, P> {
+    inner: Property<T, RwLock<P>>,
+}
+
+impl<T: Extend, P: Send + Sync + Default + 'static> SyncProperty<T, P> {
+    /// Creates a new property whose values are initialized to [`Default::default()`].
+    pub fn new() -> Self {
+        Self::with_init(|_| Default::default())
+    }
+}
+
+impl<T: Extend, P: Send + Sync + Clone + 'static> SyncProperty<T, P> {
+    /// Creates a new property whose values are initialized to a copy of `value`.
+    pub fn with_const_init(value: P) -> Self {
+        Self::with_init(move |_| value.clone())
+    }
+}
+
+impl<T: Extend, P: Send + Sync> SyncProperty<T, P> {
+    /// Creates a new property whose values are initialized by calling `init` on the object they
+    /// are for.
+    pub fn with_init(init: impl Fn(&T) -> P + Send + Sync + 'static) -> Self
+    where
+        P: 'static,
+    {
+        SyncProperty {
+            inner: Property::with_init(move |obj| RwLock::new(init(obj))),
+        }
+    }
+
+    /// Locks the value of this property on the given object for shared access, blocking while
+    /// another thread has exclusive access. If the property has never been accessed before, its
+    /// value will be initialized using the property's initializer.
+    pub fn read<'a>(&'a self, obj: &'a T) -> LockResult<RwLockReadGuard<'a, P>> {
+        self.inner.get(obj).read()
+    }
+
+    /// Locks the value of this property on the given object for exclusive access, blocking while
+    /// any other thread has access. If the property has never been accessed before, its value
+    /// will be initialized using the property's initializer.
+    pub fn write<'a>(&'a self, obj: &'a T) -> LockResult<RwLockWriteGuard<'a, P>> {
+        // Invalidate after locking, so that computations which read the old value can't finish
+        // afterwards
+        let guard = self.inner.get(obj).write();
+        computed::invalidate(obj, &self.inner.info);
+        guard
+    }
+
+    /// Locks the value of this property on the given object for exclusive access, like a
+    /// [`Mutex`]. This is equivalent to [`SyncProperty::write`].
+    pub fn lock<'a>(&'a self, obj: &'a T) -> LockResult<RwLockWriteGuard<'a, P>> {
+        self.write(obj)
+    }
+
+    /// Sets the value of this property on the given object, waiting for exclusive access to it.
+    /// The value is set even if the lock was poisoned, and the poisoning is cleared.
+    pub fn set(&self, obj: &T, value: P) {
+        let lock = self.inner.get(obj);
+        let mut guard = lock.write().unwrap_or_else(PoisonError::into_inner);
+        computed::invalidate(obj, &self.inner.info);
+        *guard = value;
+        drop(guard);
+        lock.clear_poison();
+    }
+}
+
+impl<T: Extend, P: Send + Sync + Default + 'static> Default for SyncProperty<T, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,4 +1,5 @@
 use crate::*;
+use std::any::TypeId;
 use std::cell::Cell;
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
@@ -48,32 +49,706 @@ impl Drop for DropCounter {
 fn test_drop() {
     let mut tracker = Arc::new(());
     {
-        let prop_a = Property::new();
+        let new_prop = || {
+            let tracker = tracker.clone();
+            Property::with_init(move |_| DropCounter::new(tracker.clone()))
+        };
+        let prop_a = new_prop();
         let dynamic_a = Dynamic::new();
-        let prop_b = Property::new();
-        let init = || DropCounter::new(tracker.clone());
-        prop_a.get_with_init(&dynamic_a, init).touch();
-        prop_b.get_with_init(&dynamic_a, init).touch();
+        let prop_b = new_prop();
+        prop_a.get(&dynamic_a).touch();
+        prop_b.get(&dynamic_a).touch();
         let dynamic_b = Dynamic::new();
-        prop_b.get_with_init(&dynamic_b, init).touch();
+        prop_b.get(&dynamic_b).touch();
         drop(dynamic_a);
-        prop_a.get_with_init(&dynamic_b, init).touch();
+        prop_a.get(&dynamic_b).touch();
         drop(prop_b);
     }
     assert!(Arc::get_mut(&mut tracker).is_some());
 }
 
+#[test]
+fn test_drop_prop() {
+    let mut tracker = Arc::new(());
+    let dynamic = Dynamic::new();
+    let prop = {
+        let tracker = tracker.clone();
+        Property::with_init(move |_| DropCounter::new(tracker.clone()))
+    };
+    prop.get(&dynamic).touch();
+    assert!(Arc::get_mut(&mut tracker).is_none());
+    drop(prop);
+    assert!(Arc::get_mut(&mut tracker).is_some());
+}
+
+#[test]
+fn test_drop_nested() {
+    // Dropping values which own objects of the same subject shouldn't deadlock
+    let mut children = Property::<Dynamic, Vec<Dynamic>>::new();
+    let parent = Dynamic::new();
+    children.set(&parent, vec![Dynamic::new(), Dynamic::new()]);
+    drop(children);
+    let mut children = Property::<Dynamic, Vec<Dynamic>>::new();
+    children.set(&parent, vec![Dynamic::new()]);
+    drop(parent);
+}
+
+#[test]
+#[should_panic(expected = "read while one of them is being mutated")]
+fn test_clone_while_mutating() {
+    let mut prop = Property::<Dynamic, u32>::new();
+    let obj = Dynamic::new();
+    let value = prop.get_mut(&obj);
+    let _clone = obj.clone();
+    drop(value);
+}
+
+#[test]
+fn test_take() {
+    let mut tracker = Arc::new(());
+    let dynamic = Dynamic::new();
+    let mut prop = Property::with_init(|_| unreachable!());
+    assert!(!prop.is_set(&dynamic));
+    assert!(prop.try_get(&dynamic).is_none());
+    assert!(prop.take(&dynamic).is_none());
+    prop.set(&dynamic, DropCounter::new(tracker.clone()));
+    assert!(prop.is_set(&dynamic));
+    prop.try_get(&dynamic).unwrap().touch();
+    let value = prop.take(&dynamic).unwrap();
+    assert!(!prop.is_set(&dynamic));
+    value.touch();
+    prop.set(&dynamic, value);
+    prop.unset(&dynamic);
+    assert!(!prop.is_set(&dynamic));
+    assert!(Arc::get_mut(&mut tracker).is_some());
+    prop.get_with_init(&dynamic, || DropCounter::new(tracker.clone()))
+        .touch();
+    assert!(prop.is_set(&dynamic));
+}
+
+#[derive(Extend)]
+struct ReuseThing {
+    #[prop_data]
+    prop_data: PropertyData<ReuseThing>,
+}
+
+#[test]
+fn test_reuse_prop() {
+    let obj = ReuseThing {
+        prop_data: PropertyData::new(),
+    };
+    let mut keep = Property::new();
+    keep.set(&obj, 7u8);
+    for i in 0..1000u64 {
+        let mut prop_a = Property::new();
+        let mut prop_b = Property::new();
+        prop_a.set(&obj, i);
+        prop_b.set(&obj, [i as u16; 3]);
+        assert_eq!(*prop_a.get(&obj), i);
+        assert_eq!(*prop_b.get(&obj), [i as u16; 3]);
+    }
+    assert_eq!(*keep.get(&obj), 7);
+    let subject = ReuseThing::subject().info.lock().unwrap();
+    assert_eq!(subject.next_chunk_id, 1);
+}
+
+#[derive(Extend)]
+struct PackThing {
+    #[prop_data]
+    prop_data: PropertyData<PackThing>,
+}
+
+#[test]
+fn test_pack_props() {
+    let mut tracker = Arc::new(());
+    let obj = PackThing {
+        prop_data: PropertyData::new(),
+    };
+
+    // Small values should be packed densely, beyond the number of bits in a word
+    let mut flags = Vec::new();
+    for i in 0..100 {
+        let mut flag = Property::new();
+        flag.set(&obj, i % 3 == 0);
+        flags.push(flag);
+    }
+    for (i, flag) in flags.iter().enumerate() {
+        assert_eq!(*flag.get(&obj), i % 3 == 0);
+    }
+
+    // Large values should be stored out-of-line
+    let mut large = Property::with_init(|_| unreachable!());
+    large.set(&obj, (DropCounter::new(tracker.clone()), [7u64; 64]));
+    let (counter, values) = large.take(&obj).unwrap();
+    counter.touch();
+    assert_eq!(values, [7u64; 64]);
+    large.set(&obj, (counter, values));
+    assert_eq!(large.get(&obj).1, [7u64; 64]);
+    let subject = PackThing::subject().info.lock().unwrap();
+    assert_eq!(subject.next_chunk_id, 1);
+    drop(subject);
+    drop(large);
+    assert!(Arc::get_mut(&mut tracker).is_some());
+}
+
+#[test]
+fn test_many_chunks() {
+    let dynamic = Dynamic::new();
+    let mut props = Vec::new();
+    for i in 0..100 {
+        let mut prop = Property::with_const_init([0; 200]);
+        prop.set(&dynamic, [i as u8; 200]);
+        props.push(prop);
+    }
+    for (i, prop) in props.iter().enumerate() {
+        assert_eq!(*prop.get(&dynamic), [i as u8; 200]);
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_recursive_init() {
+    let dynamic = Dynamic::new();
+    let prop = Property::<Dynamic, u32>::new();
+    prop.get_with_init(&dynamic, || *prop.get(&dynamic) + 1);
+}
+
+#[test]
+fn test_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Dynamic>();
+    assert_send_sync::<Extended<u32>>();
+    assert_send_sync::<PropertyData<Dynamic>>();
+    assert_send_sync::<Property<Dynamic, Mutex<u32>>>();
+}
+
+#[test]
+fn test_concurrent_init() {
+    let dynamic = Dynamic::new();
+    let prop = Property::<Dynamic, usize>::new();
+    let num_inits = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                let value = prop.get_with_init(&dynamic, || {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                    num_inits.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 42
+                });
+                assert_eq!(*value, 42);
+            });
+        }
+    });
+    assert_eq!(num_inits.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
 // Generics should have different subjects for each generic parameter, since this will prevent
 // inapplicable properties from taking up space in the PropertyData.
 #[test]
-#[ignore]
 fn test_generic_subject() {
-    // TODO
     let subject_a = Extended::<u32>::subject();
     let subject_b = Extended::<f32>::subject();
     assert_ne!(subject_a as *const Subject, subject_b as *const Subject);
 }
 
+#[derive(Extend)]
+struct TupleThing(u32, #[prop_data] PropertyData<TupleThing>);
+
+#[derive(Extend)]
+enum EnumThing<T> {
+    Named {
+        #[prop_data]
+        prop_data: PropertyData<EnumThing<T>>,
+        value: T,
+    },
+    Unnamed(T, #[prop_data] PropertyData<EnumThing<T>>),
+}
+
+#[derive(Extend)]
+struct ViewThing<'a> {
+    name: &'a str,
+    #[prop_data]
+    prop_data: PropertyData<ViewThing<'a>>,
+}
+
+#[test]
+fn test_derive_shapes() {
+    let mut prop = Property::<TupleThing, u32>::new();
+    let thing = TupleThing(1, PropertyData::new());
+    prop.set(&thing, thing.0 + 1);
+    assert_eq!(*prop.get(&thing), 2);
+
+    let mut prop = Property::<EnumThing<u32>, u32>::new();
+    let named = EnumThing::Named {
+        prop_data: PropertyData::new(),
+        value: 1,
+    };
+    let unnamed = EnumThing::Unnamed(2, PropertyData::new());
+    prop.set(&named, 3);
+    prop.set(&unnamed, 4);
+    assert_eq!(*prop.get(&named), 3);
+    assert_eq!(*prop.get(&unnamed), 4);
+    let (EnumThing::Named { value, .. } | EnumThing::Unnamed(value, _)) = named;
+    assert_eq!(value, 1);
+
+    // Lifetime parameters don't affect the subject
+    let name = String::from("view");
+    let view = ViewThing {
+        name: &name,
+        prop_data: PropertyData::new(),
+    };
+    let len = Property::<ViewThing, usize>::with_init(|view| view.name.len());
+    assert_eq!(*len.get(&view), 4);
+}
+
+#[derive(Extend)]
+struct ForwardThing<T> {
+    #[prop_data(forward)]
+    inner: Extended<T>,
+    extra: u32,
+}
+
+#[test]
+fn test_derive_forward() {
+    assert!(std::ptr::eq(
+        ForwardThing::<u32>::subject(),
+        Extended::<u32>::subject()
+    ));
+    let mut inner_prop = Property::<Extended<u32>, u32>::new();
+    let mut outer_prop = Property::<ForwardThing<u32>, u32>::new();
+    let thing = ForwardThing {
+        inner: Extended::new(1),
+        extra: 2,
+    };
+    inner_prop.set(&thing.inner, 3);
+    outer_prop.set(&thing, thing.extra + 2);
+    assert_eq!(*inner_prop.get(&thing.inner), 3);
+    assert_eq!(*outer_prop.get(&thing), 4);
+    assert!(outer_prop.take(&thing).is_some());
+    assert_eq!(*inner_prop.get(&thing.inner), 3);
+}
+
+#[test]
+fn test_clone_policy() {
+    let mut tracker = Arc::new(());
+    {
+        let mut prop_clone = Property::new().with_clone_policy(ClonePolicy::Clone);
+        let prop_reset = Property::new().with_clone_policy(ClonePolicy::Reset);
+        let mut prop_share = Property::new().with_clone_policy(ClonePolicy::Share);
+        let prop_large =
+            Property::with_init(|_| unreachable!()).with_clone_policy(ClonePolicy::Clone);
+        let dynamic = Dynamic::new();
+        prop_clone.set(&dynamic, vec![tracker.clone()]);
+        prop_reset.get_with_init(&dynamic, || tracker.clone());
+        prop_share.set(&dynamic, vec![tracker.clone()]);
+        prop_large.get_with_init(&dynamic, || (tracker.clone(), [0u64; 64]));
+        let clone = dynamic.clone();
+        assert_eq!(Arc::strong_count(&tracker), 7);
+        assert_eq!(prop_clone.try_get(&clone).map(Vec::len), Some(1));
+        assert!(!prop_reset.is_set(&clone));
+        assert!(std::ptr::eq(
+            prop_share.get(&dynamic),
+            prop_share.get(&clone)
+        ));
+        assert!(prop_large.is_set(&clone));
+
+        // Mutating a shared value should not affect the other object
+        prop_share.get_mut(&clone).push(tracker.clone());
+        assert_eq!(prop_share.get(&dynamic).len(), 1);
+        assert_eq!(prop_share.get(&clone).len(), 2);
+        prop_clone.get_mut(&clone).clear();
+        assert_eq!(prop_clone.get(&dynamic).len(), 1);
+        assert_eq!(prop_share.take(&dynamic).map(|v| v.len()), Some(1));
+    }
+    assert!(Arc::get_mut(&mut tracker).is_some());
+}
+
+#[test]
+fn test_clone_nested() {
+    // Cloning values which contain objects of the same subject shouldn't deadlock
+    let mut name = Property::<Dynamic, &str>::new();
+    let mut children =
+        Property::<Dynamic, Vec<Dynamic>>::new().with_clone_policy(ClonePolicy::Clone);
+    let parent = Dynamic::new();
+    let child = Dynamic::new();
+    name.set(&child, "child");
+    children.set(&parent, vec![child]);
+    let clone = parent.clone();
+    assert_eq!(children.get(&clone).len(), 1);
+    assert!(!name.is_set(&children.get(&clone)[0]));
+}
+
+#[test]
+fn test_drop_prop_while_cloning() {
+    // A property dropped while an object is being cloned keeps its values until the clone is done
+    static DROPPED: Mutex<Option<Property<Dynamic, Vec<u32>>>> = Mutex::new(None);
+    struct DropOnClone;
+    impl Clone for DropOnClone {
+        fn clone(&self) -> Self {
+            drop(DROPPED.lock().unwrap().take());
+            DropOnClone
+        }
+    }
+    let mut trigger = Property::<Dynamic, DropOnClone>::with_init(|_| DropOnClone)
+        .with_clone_policy(ClonePolicy::Clone);
+    let mut dropped = Property::<Dynamic, Vec<u32>>::new().with_clone_policy(ClonePolicy::Clone);
+    let mut other = Property::<Dynamic, Vec<u32>>::new().with_clone_policy(ClonePolicy::Clone);
+    let obj = Dynamic::new();
+    dropped.set(&obj, vec![1]);
+    trigger.set(&obj, DropOnClone);
+    other.set(&obj, vec![2]);
+    *DROPPED.lock().unwrap() = Some(dropped);
+    let clone = obj.clone();
+    assert_eq!(*other.get(&clone), [2]);
+    let mut reused = Property::<Dynamic, Vec<u32>>::new();
+    assert!(!reused.is_set(&obj));
+    assert!(!reused.is_set(&clone));
+    reused.set(&clone, vec![3]);
+    drop((trigger, other));
+}
+
+#[derive(Extend, Clone)]
+struct CloneThing {
+    name: String,
+    #[prop_data]
+    prop_data: PropertyData<CloneThing>,
+}
+
+#[test]
+fn test_derive_clone() {
+    let prop = Property::new().with_clone_policy(ClonePolicy::Clone);
+    let thing = CloneThing {
+        name: "Foo".to_string(),
+        prop_data: PropertyData::new(),
+    };
+    prop.get_with_init(&thing, || 42);
+    let clone = thing.clone();
+    assert_eq!(clone.name, "Foo");
+    assert_eq!(prop.try_get(&clone), Some(&42));
+}
+
+#[derive(Extend, Debug)]
+struct DebugThing {
+    id: u32,
+    #[prop_data]
+    prop_data: PropertyData<DebugThing>,
+}
+
+#[test]
+fn test_debug() {
+    let thing = DebugThing {
+        id: 1,
+        prop_data: PropertyData::new(),
+    };
+    let mut name = Property::new().with_name("name");
+    let tags = Property::with_const_init(vec!["a", "b"]).with_name(String::from("tags"));
+    let mut unnamed = Property::new();
+    assert_eq!(
+        format!("{:?}", thing),
+        "DebugThing { id: 1, prop_data: PropertyData }"
+    );
+    name.set(&thing, "Foo");
+    tags.get(&thing);
+    assert_eq!(
+        format!("{:?}", thing),
+        r#"DebugThing { id: 1, prop_data: PropertyData { name: "Foo", tags: ["a", "b"] } }"#
+    );
+    unnamed.set(&thing, 5);
+    let clone_name = name.with_clone_policy(ClonePolicy::Clone);
+    assert_eq!(
+        format!("{:?}", thing),
+        r#"DebugThing { id: 1, prop_data: PropertyData { tags: ["a", "b"], .. } }"#
+    );
+    clone_name.get(&thing);
+    assert_eq!(thing.id, 1);
+    assert!(format!("{:?}", thing).contains(r#"name: """#));
+}
+
+#[test]
+fn test_debug_nested() {
+    // Formatting values which contain objects of the same subject shouldn't deadlock
+    let mut name = Property::<Dynamic, &str>::new().with_name("nested_name");
+    let mut children = Property::<Dynamic, Vec<Dynamic>>::new().with_name("nested_children");
+    let parent = Dynamic::new();
+    let child = Dynamic::new();
+    name.set(&child, "child");
+    children.set(&parent, vec![child]);
+    assert_eq!(
+        format!("{:?}", parent),
+        "Dynamic { prop_data: PropertyData { nested_children: [Dynamic { prop_data: \
+        PropertyData { nested_name: \"child\" } }] } }"
+    );
+}
+
+#[derive(Extend)]
+struct IntrospectThing {
+    #[prop_data]
+    prop_data: PropertyData<IntrospectThing>,
+}
+
+#[test]
+fn test_properties() {
+    let subject = IntrospectThing::subject();
+    assert!(subject.properties().is_empty());
+    let flag = Property::<IntrospectThing, bool>::new();
+    let name = Property::<IntrospectThing, String>::new().with_name("name");
+    let large = Property::<IntrospectThing, [u64; 64]>::with_const_init([0; 64]);
+    let props = subject.properties();
+    assert_eq!(props.len(), 3);
+    let flag_desc = props
+        .iter()
+        .find(|desc| desc.type_id == TypeId::of::<bool>());
+    let flag_desc = flag_desc.unwrap();
+    assert!(flag_desc.name.is_none());
+    assert_eq!(flag_desc.type_name, "bool");
+    assert_eq!((flag_desc.size, flag_desc.align), (1, 1));
+    assert!(!flag_desc.needs_drop);
+    let name_desc = props.iter().find(|desc| desc.name.is_some()).unwrap();
+    assert_eq!(name_desc.name.as_deref(), Some("name"));
+    assert_eq!(name_desc.type_id, TypeId::of::<String>());
+    assert!(name_desc.needs_drop);
+    let large_desc = props.iter().find(|desc| desc.size == 512).unwrap();
+    assert_eq!(large_desc.type_id, TypeId::of::<[u64; 64]>());
+    assert!(props.iter().all(|desc| desc.chunk == 0));
+    drop((flag, name, large));
+    assert!(subject.properties().is_empty());
+}
+
+#[test]
+fn test_observe() {
+    let mut prop = Property::<Dynamic, u32>::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let global_log = log.clone();
+    let global = prop.observe(move |_, old, new| {
+        global_log
+            .lock()
+            .unwrap()
+            .push(("global", old.copied(), *new))
+    });
+    let obj_a = Dynamic::new();
+    let obj_b = Dynamic::new();
+    let object_log = log.clone();
+    let object = prop.observe_object(&obj_b, move |_, old, new| {
+        object_log
+            .lock()
+            .unwrap()
+            .push(("object", old.copied(), *new))
+    });
+    prop.set(&obj_a, 1);
+    *prop.get_mut(&obj_a) += 1;
+    prop.set(&obj_b, 5);
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            ("global", None, 1),
+            ("global", Some(1), 2),
+            ("global", None, 5),
+            ("object", None, 5)
+        ]
+    );
+    log.lock().unwrap().clear();
+
+    // Observers for an object should not apply to its clones
+    let obj_c = obj_b.clone();
+    assert!(prop.unobserve(global));
+    assert!(!prop.unobserve(global));
+    prop.set(&obj_c, 6);
+    *prop.get_mut(&obj_b) = 7;
+    assert_eq!(*log.lock().unwrap(), [("object", Some(5), 7)]);
+    assert!(!prop.unobserve_object(&obj_c, object));
+    assert!(prop.unobserve_object(&obj_b, object));
+    prop.set(&obj_b, 8);
+    assert_eq!(log.lock().unwrap().len(), 1);
+
+    // Object observers should be dropped with the object
+    let tracker = Arc::new(());
+    let object_tracker = tracker.clone();
+    prop.observe_object(&obj_b, move |_, _, _| {
+        let _ = &object_tracker;
+    });
+    assert_eq!(Arc::strong_count(&tracker), 2);
+    drop(obj_b);
+    assert_eq!(Arc::strong_count(&tracker), 1);
+}
+
+#[test]
+fn test_computed() {
+    use std::sync::RwLock;
+    let a = Arc::new(RwLock::new(Property::<Dynamic, u32>::new()));
+    let b = Arc::new(RwLock::new(Property::<Dynamic, u32>::new()));
+    let num_computes = Arc::new(AtomicUsize::new(0));
+    let sum = Arc::new(ComputedProperty::new({
+        let (a, b, num_computes) = (a.clone(), b.clone(), num_computes.clone());
+        move |obj| {
+            num_computes.fetch_add(1, Ordering::Relaxed);
+            *a.read().unwrap().get(obj) + *b.read().unwrap().get(obj)
+        }
+    }));
+    let double = ComputedProperty::new({
+        let sum = sum.clone();
+        move |obj| *sum.get(obj) * 2
+    });
+    let obj = Dynamic::new();
+    let other = Dynamic::new();
+    a.write().unwrap().set(&obj, 1);
+    b.write().unwrap().set(&obj, 2);
+    assert_eq!(*sum.get(&obj), 3);
+    assert_eq!(*sum.get(&obj), 3);
+    assert_eq!(num_computes.load(Ordering::Relaxed), 1);
+    assert_eq!(*double.get(&obj), 6);
+
+    // Changes to inputs on other objects should not invalidate the value
+    a.write().unwrap().set(&other, 10);
+    assert!(sum.is_cached(&obj));
+
+    // Changing an input should invalidate dependent values, including nested ones
+    *b.write().unwrap().get_mut(&obj) += 1;
+    assert!(!sum.is_cached(&obj));
+    assert!(!double.is_cached(&obj));
+    assert_eq!(*double.get(&obj), 8);
+    assert_eq!(num_computes.load(Ordering::Relaxed), 2);
+    a.write().unwrap().unset(&obj);
+    assert_eq!(*sum.get(&obj), 3);
+
+    // Manually invalidating a value should invalidate values that depend on it
+    assert_eq!(*double.get(&obj), 6);
+    sum.invalidate(&obj);
+    assert!(!double.is_cached(&obj));
+    assert_eq!(*sum.get(&obj), 3);
+    assert_eq!(num_computes.load(Ordering::Relaxed), 4);
+
+    // Reading an input several times records it once, and internal properties are hidden
+    let twice = ComputedProperty::new({
+        let a = a.clone();
+        move |obj| *a.read().unwrap().get(obj) + *a.read().unwrap().get(obj)
+    });
+    assert_eq!(*twice.get(&other), 20);
+    assert_eq!(computed::num_dependencies(&other), 1);
+    assert!(Dynamic::subject()
+        .properties()
+        .iter()
+        .all(|desc| !desc.type_name.contains("Dependency")));
+}
+
+#[test]
+#[should_panic(expected = "depends on itself")]
+fn test_computed_cycle() {
+    static PROP: OnceLock<ComputedProperty<Dynamic, u32>> = OnceLock::new();
+    let prop = PROP.get_or_init(|| ComputedProperty::new(|obj| *PROP.get().unwrap().get(obj) + 1));
+    prop.get(&Dynamic::new());
+}
+
+#[cfg(feature = "serde")]
+#[derive(Extend)]
+struct SerdeThing {
+    #[prop_data]
+    prop_data: PropertyData<SerdeThing>,
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let thing = SerdeThing {
+        prop_data: PropertyData::new(),
+    };
+    let mut name = Property::<SerdeThing, String>::new().with_key("serde_name");
+    let mut tags = Property::<SerdeThing, Vec<String>>::new()
+        .with_clone_policy(ClonePolicy::Share)
+        .with_key("serde_tags");
+    let mut large =
+        Property::<SerdeThing, Vec<u64>>::with_init(|_| vec![0; 64]).with_key("serde_large");
+    let mut unkeyed = Property::<SerdeThing, u32>::new();
+    name.set(&thing, "Foo".to_string());
+    tags.set(&thing, vec!["a".to_string()]);
+    unkeyed.set(&thing, 5);
+    let json = serde_json::to_value(&thing.prop_data).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({ "serde_name": "Foo", "serde_tags": ["a"] })
+    );
+    let copy = SerdeThing {
+        prop_data: serde_json::from_value(json).unwrap(),
+    };
+    assert_eq!(name.try_get(&copy).unwrap(), "Foo");
+    assert_eq!(tags.try_get(&copy).unwrap(), &["a"]);
+    assert!(!unkeyed.is_set(&copy));
+    assert!(!large.is_set(&copy));
+    large.get_mut(&copy)[0] = 7;
+    let json = serde_json::to_value(&copy.prop_data).unwrap();
+    assert_eq!(json["serde_large"][0], 7);
+    let copy = SerdeThing {
+        prop_data: serde_json::from_value(json).unwrap(),
+    };
+    assert_eq!(large.get(&copy)[0], 7);
+    assert_eq!(tags.take(&copy).unwrap(), &["a"]);
+    name.unset(&thing);
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for SerdeThing {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.prop_data.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SerdeThing {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let prop_data = PropertyData::deserialize(deserializer)?;
+        Ok(SerdeThing { prop_data })
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_nested() {
+    // Values which contain objects of the same subject shouldn't deadlock
+    let mut name = Property::<SerdeThing, String>::new().with_key("serde_nested_name");
+    let mut children =
+        Property::<SerdeThing, Vec<SerdeThing>>::new().with_key("serde_nested_children");
+    let parent = SerdeThing {
+        prop_data: PropertyData::new(),
+    };
+    let child = SerdeThing {
+        prop_data: PropertyData::new(),
+    };
+    name.set(&child, "child".to_string());
+    children.set(&parent, vec![child]);
+    let json = serde_json::to_value(&parent).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({ "serde_nested_children": [{ "serde_nested_name": "child" }] })
+    );
+    let copy: SerdeThing = serde_json::from_value(json).unwrap();
+    assert_eq!(name.get(&children.get(&copy)[0]), "child");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_unknown() {
+    let prop = Property::<SerdeThing, u32>::new().with_key("serde_known");
+    let json = serde_json::json!({ "serde_known": 1, "serde_unknown": [1, 2] });
+    let res = serde_json::from_value::<PropertyData<SerdeThing>>(json.clone());
+    assert!(res.unwrap_err().to_string().contains("serde_unknown"));
+    let data =
+        PropertyData::<SerdeThing>::deserialize_preserving::<serde_json::Value, _>(json.clone())
+            .unwrap();
+    let thing = SerdeThing { prop_data: data };
+    assert_eq!(prop.try_get(&thing), Some(&1));
+    assert_eq!(serde_json::to_value(&thing.prop_data).unwrap(), json);
+    let res = serde_json::from_str::<PropertyData<SerdeThing>>(
+        r#"{ "serde_known": 1, "serde_known": 2 }"#,
+    );
+    assert!(res.unwrap_err().to_string().contains("duplicate"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+#[should_panic]
+fn test_serde_duplicate_key() {
+    let _a = Property::<SerdeThing, u32>::new().with_key("serde_duplicate");
+    let _b = Property::<SerdeThing, u32>::new().with_key("serde_duplicate");
+}
+
 #[derive(Extend)]
 struct MemoizeThing {
     num_reads: AtomicUsize,
@@ -118,3 +793,479 @@ fn test_memoize() {
     *const_mutex_hello(&obj).lock().unwrap() = "World";
     assert_eq!(*const_mutex_hello(&obj).lock().unwrap(), "World");
 }
+
+#[memoize(capacity = 3)]
+fn sum_reads(obj: &MemoizeThing, a: u32, (b, c): (u32, u32)) -> usize {
+    obj.num_reads
+        .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    (a + b + c) as usize
+}
+
+#[memoize]
+fn fib(obj: &MemoizeThing, n: u64) -> u64 {
+    if n < 2 {
+        n
+    } else {
+        fib(obj, n - 1) + fib(obj, n - 2)
+    }
+}
+
+#[test]
+fn test_memoize_args() {
+    let mut obj = MemoizeThing {
+        num_reads: AtomicUsize::new(0),
+        prop_data: PropertyData::new(),
+    };
+    assert_eq!(sum_reads(&obj, 1, (2, 3)), 6);
+    assert_eq!(sum_reads(&obj, 1, (2, 3)), 6);
+    assert_eq!(sum_reads(&obj, 2, (2, 3)), 7);
+    assert_eq!(obj.num_reads.load(std::sync::atomic::Ordering::SeqCst), 2);
+    assert!(is_cached_sum_reads(&obj, 1, (2, 3)));
+
+    // Exceeding the capacity should evict the least recently used result
+    assert_eq!(sum_reads(&obj, 3, (0, 0)), 3);
+    assert_eq!(sum_reads(&obj, 1, (2, 3)), 6);
+    assert_eq!(sum_reads(&obj, 4, (0, 0)), 4);
+    assert_eq!(sum_reads_property().get(&obj).len(), 3);
+    assert!(is_cached_sum_reads(&obj, 1, (2, 3)));
+    assert!(!is_cached_sum_reads(&obj, 2, (2, 3)));
+    assert!(invalidate_sum_reads(&mut obj));
+    assert!(!is_cached_sum_reads(&obj, 1, (2, 3)));
+    assert!(!invalidate_sum_reads(&mut obj));
+
+    // Memoized functions may call themselves with different arguments
+    assert_eq!(fib(&obj, 80), 23416728348467685);
+    assert_eq!(fib_property().get(&obj).len(), 81);
+
+    // Invalidating during a savepoint can be rolled back
+    let savepoint = Savepoint::new(&obj);
+    assert!(invalidate_fib(&mut obj));
+    assert!(!is_cached_fib(&obj, 80));
+    savepoint.rollback(&mut obj).unwrap();
+    assert!(is_cached_fib(&obj, 80));
+}
+
+impl MemoizeThing {
+    #[memoize]
+    fn num_reads_plus(&self, n: usize) -> usize {
+        self.num_reads
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        n + 1
+    }
+
+    #[memoize(share)]
+    fn label(&self) -> &String {
+        format!("thing {}", self.num_reads_plus(0))
+    }
+}
+
+#[derive(Extend)]
+struct MemoizeWrapper<T> {
+    value: T,
+    #[prop_data]
+    prop_data: PropertyData<MemoizeWrapper<T>>,
+}
+
+impl<T: Clone + Send + Sync + 'static> MemoizeWrapper<T> {
+    #[memoize]
+    fn pair(&self) -> (T, T) {
+        (self.value.clone(), self.value.clone())
+    }
+}
+
+trait Describe {
+    fn describe(&self) -> String;
+}
+
+#[memoize]
+impl<T: fmt::Debug + Send + Sync + 'static> Describe for MemoizeWrapper<T> {
+    #[memoize]
+    fn describe(&self) -> String {
+        format!("{:?}", self.value)
+    }
+}
+
+#[test]
+fn test_memoize_methods() {
+    let mut obj = MemoizeThing {
+        num_reads: AtomicUsize::new(0),
+        prop_data: PropertyData::new(),
+    };
+    assert_eq!(obj.num_reads_plus(1), 2);
+    assert_eq!(obj.num_reads_plus(1), 2);
+    assert!(obj.is_cached_num_reads_plus(1));
+    assert_eq!(obj.label(), "thing 1");
+    assert!(std::ptr::eq(obj.label(), obj.label()));
+    assert_eq!(obj.num_reads.load(std::sync::atomic::Ordering::SeqCst), 2);
+    assert!(obj.invalidate_label());
+    assert!(!obj.is_cached_label());
+    assert!(MemoizeThing::label_property().try_get(&obj).is_none());
+
+    // The property can also initialize the value itself
+    assert_eq!(MemoizeThing::label_property().get(&obj), "thing 1");
+    assert!(obj.is_cached_label());
+    assert!(std::ptr::eq(
+        obj.label(),
+        MemoizeThing::label_property().get(&obj)
+    ));
+
+    // Generic types should have separate properties for each instantiation
+    let a = MemoizeWrapper {
+        value: 1u32,
+        prop_data: PropertyData::new(),
+    };
+    let mut b = MemoizeWrapper {
+        value: "b",
+        prop_data: PropertyData::new(),
+    };
+    assert_eq!(a.pair(), (1, 1));
+    assert_eq!(b.pair(), ("b", "b"));
+    assert_eq!(a.describe(), "1");
+    assert_eq!(b.describe(), "\"b\"");
+    assert!(b.is_cached_describe());
+    assert!(b.invalidate_describe());
+    assert!(!b.is_cached_describe());
+    assert!(a.is_cached_describe());
+    assert_eq!(MemoizeWrapper::<&str>::describe_property().get(&b), "\"b\"");
+}
+
+#[test]
+fn test_memoize_invalidate() {
+    let mut obj = MemoizeThing {
+        num_reads: AtomicUsize::new(0),
+        prop_data: PropertyData::new(),
+    };
+    assert!(!is_cached_const_123(&obj));
+    assert!(!invalidate_const_123(&mut obj));
+    assert_eq!(const_123(&obj), 123);
+    assert!(is_cached_const_123(&obj));
+    assert_eq!(const_123_property().try_get(&obj), Some(&123));
+    assert!(invalidate_const_123(&mut obj));
+    assert!(!is_cached_const_123(&obj));
+    assert_eq!(const_123(&obj), 123);
+    assert_eq!(obj.num_reads.load(std::sync::atomic::Ordering::SeqCst), 2);
+    *const_mutex_hello(&obj).lock().unwrap() = "World";
+    invalidate_const_mutex_hello(&mut obj);
+    assert_eq!(*const_mutex_hello(&obj).lock().unwrap(), "Hello");
+}
+
+#[test]
+fn test_savepoint() {
+    let mut tracker = Arc::new(());
+    let mut a = Property::<Dynamic, u32>::new();
+    let mut b = Property::<Dynamic, Vec<u32>>::new().transactional();
+    let mut c = Property::<Dynamic, DropCounter>::with_init({
+        let tracker = tracker.clone();
+        move |_| DropCounter::new(tracker.clone())
+    });
+    let mut obj = Dynamic::new();
+    a.set(&obj, 1);
+    b.get_mut(&obj).push(1);
+
+    // Roll back a single level
+    let savepoint = Savepoint::new(&obj);
+    a.set(&obj, 2);
+    b.get_mut(&obj).push(2);
+    assert_eq!(b.take(&obj), Some(vec![1, 2]));
+    c.get(&obj).touch();
+    c.unset(&obj);
+    c.set(&obj, DropCounter::new(tracker.clone()));
+    savepoint.rollback(&mut obj).unwrap();
+    assert_eq!(*a.get(&obj), 1);
+    assert_eq!(*b.get(&obj), [1]);
+    // Initializing a value on first access is not a recorded change
+    assert!(c.is_set(&obj));
+    assert_eq!(Arc::strong_count(&tracker), 3);
+
+    // Nested savepoints
+    let outer = Savepoint::new(&obj);
+    a.set(&obj, 3);
+    let inner = Savepoint::new(&obj);
+    a.set(&obj, 4);
+    b.get_mut(&obj).push(4);
+    inner.commit();
+    a.set(&obj, 5);
+    let inner = Savepoint::new(&obj);
+    a.set(&obj, 6);
+    inner.rollback(&mut obj).unwrap();
+    assert_eq!(*a.get(&obj), 5);
+    assert_eq!(*b.get(&obj), [1, 4]);
+    outer.rollback(&mut obj).unwrap();
+    assert_eq!(*a.get(&obj), 1);
+    assert_eq!(*b.get(&obj), [1]);
+
+    // Changes are no longer recorded once all savepoints are committed
+    let savepoint = Savepoint::new(&obj);
+    a.set(&obj, 7);
+    drop(savepoint);
+    assert!(!obj.prop_data.source.is_recording());
+    assert_eq!(*a.get(&obj), 7);
+
+    // Values of dropped properties are not restored
+    let savepoint = Savepoint::new(&obj);
+    c.set(&obj, DropCounter::new(tracker.clone()));
+    drop(c);
+    let mut d = Property::<Dynamic, u32>::new();
+    d.set(&obj, 8);
+    savepoint.rollback(&mut obj).unwrap();
+    assert_eq!(*d.get(&obj), 0);
+    drop(obj);
+    assert!(Arc::get_mut(&mut tracker).is_some());
+}
+
+#[test]
+fn test_savepoint_shared() {
+    let mut prop = Property::<Dynamic, Vec<u32>>::new().with_clone_policy(ClonePolicy::Share);
+    let mut obj = Dynamic::new();
+    prop.set(&obj, vec![1]);
+    let copy = obj.clone();
+    let savepoint = Savepoint::new(&obj);
+    prop.set(&obj, vec![2]);
+    prop.set(&obj, vec![3]);
+    assert_eq!(*prop.get(&copy), [1]);
+    savepoint.rollback(&mut obj).unwrap();
+    assert_eq!(*prop.get(&obj), [1]);
+    assert_eq!(*prop.get(&copy), [1]);
+}
+
+#[test]
+fn test_savepoint_computed() {
+    let width = Arc::new(Mutex::new(Property::<Dynamic, u32>::new()));
+    let area = ComputedProperty::new({
+        let width = width.clone();
+        move |obj| *width.lock().unwrap().get(obj) * 2
+    });
+    let mut obj = Dynamic::new();
+    width.lock().unwrap().set(&obj, 3);
+    assert_eq!(*area.get(&obj), 6);
+    let savepoint = Savepoint::new(&obj);
+    width.lock().unwrap().set(&obj, 4);
+    assert_eq!(*area.get(&obj), 8);
+    savepoint.rollback(&mut obj).unwrap();
+    assert!(!area.is_cached(&obj));
+    assert_eq!(*area.get(&obj), 6);
+}
+
+#[test]
+fn test_savepoint_not_transactional() {
+    let mut prop = Property::<Dynamic, u32>::with_const_init(5).with_name("not_transactional");
+    let static_prop = Property::<Dynamic, Vec<u32>>::new();
+    let mut obj = Dynamic::new();
+    prop.set(&obj, 1);
+    static_prop.get(&obj);
+    let savepoint = Savepoint::new(&obj);
+    *prop.get_mut(&obj) += 1;
+    assert!(static_prop.discard(&mut obj));
+    assert!(!static_prop.discard(&mut obj));
+    let mut other = Property::<Dynamic, u32>::new();
+    other.set(&obj, 3);
+    *other.get_mut(&obj) += 1;
+
+    // The mutated value can't be restored, but the discarded value can, as can a value which
+    // was set before being mutated
+    let err = savepoint.rollback(&mut obj).unwrap_err();
+    let names: Vec<_> = err
+        .properties()
+        .iter()
+        .map(|desc| desc.name.clone())
+        .collect();
+    assert_eq!(names, [Some("not_transactional".into())]);
+    assert_eq!(*prop.get(&obj), 2);
+    assert!(static_prop.is_set(&obj));
+    assert!(!other.is_set(&obj));
+}
+
+#[test]
+#[should_panic(expected = "Savepoint does not apply to this object")]
+fn test_savepoint_wrong_object() {
+    let a = Dynamic::new();
+    let mut b = Dynamic::new();
+    let savepoint = Savepoint::new(&a);
+    savepoint.rollback(&mut b).unwrap();
+}
+
+#[test]
+fn test_atomic() {
+    use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64};
+    let hits = Arc::new(AtomicProperty::<Dynamic, AtomicU64>::new());
+    let flag = AtomicProperty::<Dynamic, AtomicBool>::with_const_init(true);
+    let ptr = AtomicProperty::<Dynamic, AtomicPtr<u32>>::with_init(|_| ptr::null_mut());
+    let area = ComputedProperty::new({
+        let hits = hits.clone();
+        move |obj| hits.load(obj, Ordering::Relaxed) * 2
+    });
+    let obj = Dynamic::new();
+    std::thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                for _ in 0..100 {
+                    hits.fetch_add(&obj, 1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    assert_eq!(hits.load(&obj, Ordering::Relaxed), 800);
+    assert_eq!(hits.fetch_sub(&obj, 1, Ordering::Relaxed), 800);
+    assert_eq!(hits.swap(&obj, 5, Ordering::Relaxed), 799);
+    assert!(flag.load(&obj, Ordering::Relaxed));
+    assert_eq!(
+        flag.compare_exchange(&obj, false, true, Ordering::SeqCst, Ordering::SeqCst),
+        Err(true)
+    );
+    flag.store(&obj, false, Ordering::Relaxed);
+    assert!(!flag.get(&obj).load(Ordering::Relaxed));
+    let mut value = 3;
+    assert!(ptr.load(&obj, Ordering::Relaxed).is_null());
+    ptr.store(&obj, &mut value, Ordering::Relaxed);
+    assert_eq!(unsafe { *ptr.load(&obj, Ordering::Relaxed) }, 3);
+
+    // Computed values which depend on an atomic property are invalidated when it changes
+    assert_eq!(*area.get(&obj), 10);
+    hits.fetch_add(&obj, 1, Ordering::Relaxed);
+    assert!(!area.is_cached(&obj));
+    assert_eq!(*area.get(&obj), 12);
+}
+
+#[test]
+fn test_sync() {
+    let counts = Arc::new(SyncProperty::<Dynamic, Vec<u32>>::with_const_init(vec![0]));
+    let other = SyncProperty::<Dynamic, u32>::new();
+    let total = ComputedProperty::new({
+        let counts = counts.clone();
+        move |obj| counts.read(obj).unwrap().iter().sum::<u32>()
+    });
+    let obj = Dynamic::new();
+    std::thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| {
+                for _ in 0..100 {
+                    counts.lock(&obj).unwrap()[0] += 1;
+                }
+            });
+        }
+
+        // Holding a lock on one value doesn't block access to another
+        let guard = counts.read(&obj).unwrap();
+        *other.write(&obj).unwrap() += 1;
+        drop(guard);
+    });
+    assert_eq!(*counts.read(&obj).unwrap(), [800]);
+    assert_eq!(*other.read(&obj).unwrap(), 1);
+
+    // Computed values which depend on a value are invalidated when it is written
+    assert_eq!(*total.get(&obj), 800);
+    counts.write(&obj).unwrap().push(1);
+    assert!(!total.is_cached(&obj));
+    assert_eq!(*total.get(&obj), 801);
+    counts.set(&obj, vec![2]);
+    assert_eq!(*total.get(&obj), 2);
+}
+
+#[test]
+fn test_thread_local() {
+    use std::cell::RefCell;
+    let tracker = Arc::new(());
+    let buffer = ThreadLocalProperty::<Dynamic, RefCell<Vec<u32>>>::new();
+    let counter = ThreadLocalProperty::<Dynamic, DropCounter>::with_init({
+        let tracker = tracker.clone();
+        move |_| DropCounter::new(tracker.clone())
+    });
+    let obj = Dynamic::new();
+    buffer.get(&obj).borrow_mut().push(1);
+    assert!(counter.try_get(&obj).is_none());
+    counter.get(&obj).touch();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            assert!(buffer.try_get(&obj).is_none());
+            buffer.get(&obj).borrow_mut().push(2);
+            assert_eq!(*buffer.get(&obj).borrow(), [2]);
+            counter.get(&obj).touch();
+            assert_eq!(Arc::strong_count(&tracker), 4);
+        });
+    });
+
+    // Values are released when their thread exits
+    assert_eq!(Arc::strong_count(&tracker), 3);
+    assert_eq!(*buffer.get(&obj).borrow(), [1]);
+
+    // Values which are still referenced when their thread exits are released with the reference
+    thread_local! {
+        static HELD: RefCell<Option<ThreadLocalRef<'static, DropCounter>>> =
+            const { RefCell::new(None) };
+    }
+    static OBJ: OnceLock<Dynamic> = OnceLock::new();
+    let held = ThreadLocalProperty::<Dynamic, DropCounter>::with_init({
+        let tracker = tracker.clone();
+        move |_| DropCounter::new(tracker.clone())
+    });
+    let held: &'static ThreadLocalProperty<Dynamic, DropCounter> = Box::leak(Box::new(held));
+    std::thread::spawn(move || {
+        let obj = OBJ.get_or_init(Dynamic::new);
+        HELD.with(|value| *value.borrow_mut() = Some(held.get(obj)));
+    })
+    .join()
+    .unwrap();
+    assert_eq!(Arc::strong_count(&tracker), 4);
+
+    drop(obj);
+    drop(counter);
+    assert_eq!(Arc::strong_count(&tracker), 2);
+}
+
+#[test]
+fn test_external() {
+    static STATIC_A: u32 = 1;
+    static STATIC_B: u32 = 2;
+    let mut tracker = Arc::new(());
+    let mut prop = ExternalProperty::<u32, Option<DropCounter>>::new();
+    let doubled = ExternalProperty::<u32, u32>::with_init(|value| *value * 2);
+    assert_eq!(*doubled.get(&STATIC_B), 4);
+    assert!(!doubled.is_set(&STATIC_A));
+    assert_eq!(doubled.try_get(&STATIC_B), Some(&4));
+
+    // Values for static objects
+    prop.set(&STATIC_A, Some(DropCounter::new(tracker.clone())));
+    prop.get(&STATIC_A).as_ref().unwrap().touch();
+    assert!(prop.get(&STATIC_B).is_none());
+    assert!(prop.take(&STATIC_B).unwrap().is_none());
+    assert!(!prop.is_set(&STATIC_B));
+
+    // Values for shared objects
+    let shared = Arc::new(3);
+    let clone = shared.clone();
+    *prop.get_mut(&shared) = Some(DropCounter::new(tracker.clone()));
+    prop.get(&clone).as_ref().unwrap().touch();
+    assert!(prop.get(&Arc::new(3)).is_none());
+    assert_eq!(Arc::strong_count(&tracker), 3);
+
+    // Values for dropped objects are removed when the property is purged
+    drop((shared, clone));
+    prop.purge();
+    assert_eq!(Arc::strong_count(&tracker), 2);
+
+    // Values are dropped automatically as more values are set
+    let mut objs = Vec::new();
+    for _ in 0..100 {
+        let obj = Arc::new(0);
+        prop.set(&obj, Some(DropCounter::new(tracker.clone())));
+        objs.push(obj);
+        if objs.len() > 10 {
+            objs.remove(0);
+        }
+    }
+    assert!(Arc::strong_count(&tracker) < 40);
+    drop(objs);
+    drop(prop);
+
+    // Values are also dropped when the property is only accessed through a shared reference
+    let counters = ExternalProperty::<u32, DropCounter>::with_init({
+        let tracker = tracker.clone();
+        move |_| DropCounter::new(tracker.clone())
+    });
+    for _ in 0..100 {
+        counters.get(&Arc::new(0)).touch();
+    }
+    assert!(Arc::strong_count(&tracker) < 40);
+    drop(counters);
+    assert!(Arc::get_mut(&mut tracker).is_some());
+}
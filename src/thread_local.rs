@@ -0,0 +1,201 @@
+//! Properties which have a separate value for each thread.
+use crate::*;
+use std::cell::{Cell, RefCell};
+use std::sync::Weak;
+
+/// The values of a [`ThreadLocalProperty`] on one object, for each thread which accessed it.
+struct ThreadValues<P> {
+    slots: Mutex<HashMap<ThreadId, Box<Slot<P>>>>,
+}
+
+/// The value of a [`ThreadLocalProperty`] for one thread. This is only accessed by that thread,
+/// except when it is dropped along with the object or property.
+struct Slot<P> {
+    value: P,
+
+    /// The number of [`ThreadLocalRef`]s to this slot.
+    refs: Cell<usize>,
+
+    /// Set when the thread exits while there are still references to the slot, in which case the
+    /// last reference frees it.
+    released: Cell<bool>,
+}
+
+/// Allows a thread to release its values for properties of any type when it exits.
+trait Release: Send + Sync {
+    fn release(&self, thread: ThreadId);
+}
+
+impl<P: Send> Release for ThreadValues<P> {
+    fn release(&self, thread: ThreadId) {
+        let slot = lock_ignore_poison(&self.slots).remove(&thread);
+        if let Some(slot) = slot {
+            if slot.refs.get() > 0 {
+                slot.released.set(true);
+                Box::leak(slot);
+            }
+        }
+    }
+}
+
+/// The [`ThreadValues`] which have a value for the current thread.
+struct Registrations {
+    thread: ThreadId,
+    values: Vec<Weak<dyn Release>>,
+}
+
+impl Drop for Registrations {
+    fn drop(&mut self) {
+        for values in self.values.drain(..) {
+            if let Some(values) = values.upgrade() {
+                values.release(self.thread);
+            }
+        }
+    }
+}
+
+thread_local! {
+    static REGISTRATIONS: RefCell<Registrations> = RefCell::new(Registrations {
+        thread: thread::current().id(),
+        values: Vec::new(),
+    });
+}
+
+/// Arranges for the current thread's value in `values` to be released when the thread exits.
+fn register(values: Weak<dyn Release>) {
+    // If the thread is already exiting, the value is kept until the object is dropped
+    let _ = REGISTRATIONS.try_with(|registrations| {
+        let list = &mut registrations.borrow_mut().values;
+        if list.len() == list.capacity() {
+            list.retain(|values| values.strong_count() > 0);
+        }
+        list.push(values);
+    });
+}
+
+/// A property which has a separate value for each thread. Values are only accessible from the
+/// thread they belong to, so they don't need to be [`Sync`], and interior mutability (e.g.
+/// [`RefCell`]) can be used to modify them.
+///
+/// A thread's value on an object is dropped when the thread exits, or when the object or property
+/// is dropped.
+///
+/// ## Example
+///
+/// ```
+/// use dynprops::{Dynamic, ThreadLocalProperty};
+/// use std::cell::Cell;
+///
+/// let count = ThreadLocalProperty::<Dynamic, Cell<u32>>::new();
+/// let obj = Dynamic::new();
+/// count.get(&obj).set(5);
+/// std::thread::scope(|s| {
+///     s.spawn(|| {
+///         assert_eq!(count.get(&obj).get(), 0);
+///         count.get(&obj).set(1);
+///     });
+/// });
+/// assert_eq!(count.get(&obj).get(), 5);
+/// ```
+pub struct ThreadLocalProperty<T: Extend, P> {
+    inner: Property<T, Arc<ThreadValues<P>>>,
+    init: Box<dyn Fn(&T) -> P + Send + Sync>,
+}
+
+impl<T: Extend, P: Send + Default + 'static> ThreadLocalProperty<T, P> {
+    /// Creates a new property whose values are initialized to [`Default::default()`].
+    pub fn new() -> Self {
+        Self::with_init(|_| Default::default())
+    }
+}
+
+impl<T: Extend, P: Send + 'static> ThreadLocalProperty<T, P> {
+    /// Creates a new property whose values are initialized by calling `init` on the object they
+    /// are for, on the thread they are for.
+    pub fn with_init(init: impl Fn(&T) -> P + Send + Sync + 'static) -> Self {
+        ThreadLocalProperty {
+            inner: Property::with_init(|_| {
+                Arc::new(ThreadValues {
+                    slots: Mutex::new(HashMap::new()),
+                })
+            }),
+            init: Box::new(init),
+        }
+    }
+
+    /// Gets the current thread's value of this property on the given object. If the thread has
+    /// never accessed the property on the object before, its value will be initialized using the
+    /// property's initializer.
+    pub fn get<'a>(&'a self, obj: &'a T) -> ThreadLocalRef<'a, P> {
+        let values = self.inner.get(obj);
+        let thread = thread::current().id();
+        if let Some(slot) = lock_ignore_poison(&values.slots).get(&thread) {
+            return ThreadLocalRef::new(slot);
+        }
+
+        // Don't hold the lock while initializing, since the initializer may access the object
+        let slot = Box::new(Slot {
+            value: (self.init)(obj),
+            refs: Cell::new(0),
+            released: Cell::new(false),
+        });
+        let mut slots = lock_ignore_poison(&values.slots);
+        let slot = &**slots.entry(thread).or_insert(slot);
+        let res = ThreadLocalRef::new(slot);
+        drop(slots);
+        let values: Arc<dyn Release> = values.clone();
+        register(Arc::downgrade(&values));
+        res
+    }
+
+    /// Gets the current thread's value of this property on the given object, or [`None`] if the
+    /// thread has not accessed the property on the object.
+    pub fn try_get<'a>(&'a self, obj: &'a T) -> Option<ThreadLocalRef<'a, P>> {
+        let values = self.inner.try_get(obj)?;
+        let thread = thread::current().id();
+        let slots = lock_ignore_poison(&values.slots);
+        slots.get(&thread).map(|slot| ThreadLocalRef::new(slot))
+    }
+}
+
+impl<T: Extend, P: Send + Default + 'static> Default for ThreadLocalProperty<T, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A reference to the current thread's value of a [`ThreadLocalProperty`] on an object. This
+/// can't be sent to other threads, since the value may be dropped when its thread exits.
+pub struct ThreadLocalRef<'a, P> {
+    slot: NonNull<Slot<P>>,
+    _phantom: PhantomData<(&'a P, *const ())>,
+}
+
+impl<P> ThreadLocalRef<'_, P> {
+    fn new(slot: &Slot<P>) -> Self {
+        slot.refs.set(slot.refs.get() + 1);
+        ThreadLocalRef {
+            slot: NonNull::from(slot),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<P> Drop for ThreadLocalRef<'_, P> {
+    fn drop(&mut self) {
+        let slot = unsafe { self.slot.as_ref() };
+        slot.refs.set(slot.refs.get() - 1);
+        if slot.refs.get() == 0 && slot.released.get() {
+            drop(unsafe { Box::from_raw(self.slot.as_ptr()) });
+        }
+    }
+}
+
+impl<P> Deref for ThreadLocalRef<'_, P> {
+    type Target = P;
+    fn deref(&self) -> &P {
+        // The slot can only be dropped by this thread, or along with the object or property,
+        // which are borrowed
+        unsafe { &self.slot.as_ref().value }
+    }
+}
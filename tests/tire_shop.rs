@@ -11,18 +11,32 @@ mod vehicle {
         fn tires(&self) -> Vec<&Tire>;
     }
 
+    /// Identifies the subject shared by all vehicle types, so that a single property can apply to
+    /// any kind of vehicle.
+    pub enum Vehicles {}
+
+    #[derive(Extend)]
+    #[extend(subject = Vehicles)]
     pub struct Motorcycle {
         pub front_tire: Tire,
         pub back_tire: Tire,
+        #[prop_data]
+        prop_data: PropertyData<Motorcycle>,
     }
 
+    #[derive(Extend)]
+    #[extend(subject = Vehicles)]
     pub struct Car {
         pub front_left_tire: Tire,
         pub front_right_tire: Tire,
         pub back_left_tire: Tire,
         pub back_right_tire: Tire,
+        #[prop_data]
+        prop_data: PropertyData<Car>,
     }
 
+    #[derive(Extend)]
+    #[extend(subject = Vehicles)]
     pub struct Truck {
         pub front_left_tire: Tire,
         pub front_right_tire: Tire,
@@ -30,6 +44,8 @@ mod vehicle {
         pub mid_right_tire: DualTire,
         pub back_left_tire: DualTire,
         pub back_right_tire: DualTire,
+        #[prop_data]
+        prop_data: PropertyData<Truck>,
     }
 
     impl Vehicle for Motorcycle {
@@ -116,6 +132,22 @@ mod vehicle {
                 kind: &PASSENGER_33_TIRE,
                 prop_data: PropertyData::new(),
             },
+            prop_data: PropertyData::new(),
+        }
+    }
+
+    /// Creates a new motorcycle.
+    pub fn new_motorcycle() -> Motorcycle {
+        Motorcycle {
+            front_tire: Tire {
+                kind: &PASSENGER_33_TIRE,
+                prop_data: PropertyData::new(),
+            },
+            back_tire: Tire {
+                kind: &PASSENGER_33_TIRE,
+                prop_data: PropertyData::new(),
+            },
+            prop_data: PropertyData::new(),
         }
     }
 }
@@ -123,7 +155,7 @@ mod vehicle {
 /// This module contains the code used by our tire shop.
 mod shop {
     use crate::vehicle::*;
-    use dynprops::Property;
+    use dynprops::{ExtendShared, ExternalProperty, Property, Savepoint, SharedProperty};
 
     /// The set of observations taken during a tire inspection.
     struct TireCheck {
@@ -159,6 +191,108 @@ mod shop {
         return cost;
     }
 
+    /// Records that a vehicle was serviced, returning the number of times it has been serviced.
+    fn record_service<V: Vehicle + ExtendShared<Vehicles>>(
+        vehicle: &V,
+        num_services: &mut SharedProperty<Vehicles, u32>,
+    ) -> u32 {
+        let mut count = num_services.get_mut(vehicle);
+        *count += 1;
+        *count
+    }
+
+    /// Inflates the tires of a car to the given pressure, failing if any of them is punctured.
+    fn inflate_tires(car: &Car, check: &mut TireCheck, target: f32) -> Result<(), &'static str> {
+        for tire in car.tires() {
+            if *check.notes.get(tire) == "Punctured" {
+                return Err("Can't inflate a punctured tire");
+            }
+            check.pressure.set(tire, target);
+            check.notes.set(tire, "Inflated");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_inflation() {
+        let mut car = new_passenger_car();
+        let mut check = TireCheck {
+            pressure: Property::new(),
+            tread_depth: Property::new(),
+            notes: Property::new(),
+        };
+        check.pressure.set(&car.front_left_tire, 28.0);
+        check.notes.set(&car.back_left_tire, "Punctured");
+
+        // Roll back every tire if the update fails partway through
+        let savepoints = car
+            .tires()
+            .into_iter()
+            .map(Savepoint::new)
+            .collect::<Vec<_>>();
+        assert!(inflate_tires(&car, &mut check, 33.0).is_err());
+        assert_eq!(*check.pressure.get(&car.front_left_tire), 33.0);
+        let tires = [
+            &mut car.front_left_tire,
+            &mut car.front_right_tire,
+            &mut car.back_left_tire,
+            &mut car.back_right_tire,
+        ];
+        for (savepoint, tire) in savepoints.into_iter().zip(tires) {
+            savepoint.rollback(tire).unwrap();
+        }
+        assert_eq!(*check.pressure.get(&car.front_left_tire), 28.0);
+        assert!(!check.pressure.is_set(&car.front_right_tire));
+        assert_eq!(*check.notes.get(&car.front_right_tire), "");
+        assert_eq!(*check.notes.get(&car.back_left_tire), "Punctured");
+    }
+
+    /// Replaces the worn-out tires of a vehicle using tires from stock, returning the number of
+    /// tires replaced. `TireKind` is part of the data model, so the stock of each kind is tracked
+    /// using an [`ExternalProperty`].
+    fn replace_tires(
+        vehicle: &dyn Vehicle,
+        check: &TireCheck,
+        stock: &mut ExternalProperty<TireKind, u32>,
+    ) -> u32 {
+        let mut count = 0;
+        for tire in vehicle.tires() {
+            let in_stock = stock.get_mut(tire.kind);
+            if *check.tread_depth.get(tire) < 4.0 && *in_stock > 0 {
+                *in_stock -= 1;
+                count += 1;
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn test_tire_stock() {
+        let car = new_passenger_car();
+        let mut check = TireCheck {
+            pressure: Property::new(),
+            tread_depth: Property::with_const_init(5.0),
+            notes: Property::new(),
+        };
+        check.tread_depth.set(&car.front_left_tire, 3.1);
+        check.tread_depth.set(&car.back_right_tire, 2.5);
+        let mut stock = ExternalProperty::new();
+        stock.set(&PASSENGER_33_TIRE, 1);
+        assert_eq!(replace_tires(&car, &check, &mut stock), 1);
+        assert_eq!(*stock.get(&PASSENGER_33_TIRE), 0);
+    }
+
+    #[test]
+    fn test_service_history() {
+        let car = new_passenger_car();
+        let motorcycle = new_motorcycle();
+        let mut num_services = SharedProperty::new();
+        assert_eq!(record_service(&car, &mut num_services), 1);
+        assert_eq!(record_service(&car, &mut num_services), 2);
+        assert_eq!(record_service(&motorcycle, &mut num_services), 1);
+        assert_eq!(num_services.try_get(&car), Some(&2));
+    }
+
     #[test]
     fn test_car() {
         // Create car
@@ -187,7 +321,10 @@ mod shop {
         assert_eq!(get_service_cost(&car, &check), 162.25);
 
         // Verify notes
+        assert!(!check.notes.is_set(&car.back_right_tire));
+        assert_eq!(check.notes.try_get(&car.back_right_tire), None);
         assert_eq!(*check.notes.get(&car.back_right_tire), "");
+        assert!(check.notes.is_set(&car.back_right_tire));
         assert_eq!(
             *check.notes.get(&car.back_left_tire),
             "Possible misalignment"